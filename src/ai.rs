@@ -0,0 +1,738 @@
+//! Computer-controlled snakes.
+//!
+//! A bot only looks at the [`MatchState`] left by the previous tick and queues the direction it
+//! picked on the `KeyboardDirection` of its snake, just like a key press. Since bots play from the
+//! state alone, a seed still always plays out the same with bots on the board. Bots running as
+//! their own program or server are the exception, see [`ProcessBot`] and [`BattlesnakeBot`]. They
+//! answer on their own thread, see [`BackgroundBot`].
+
+mod autopilot;
+mod background;
+mod battlesnake;
+mod neural;
+mod process;
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+
+use clap::ValueEnum;
+
+use serde::Deserialize;
+
+use crate::{
+    headless::{MatchState, SnakeState},
+    rules::{apply_tick_inputs, GameTick, TickSet},
+    setup_death_animation, BoardSize, FruitType, GameState, KeyboardDirection, Player,
+    SnakeDirection,
+};
+
+use autopilot::{AutopilotBot, Cycle};
+
+pub use autopilot::AutopilotPlugin;
+pub use background::BackgroundBot;
+pub use battlesnake::BattlesnakeBot;
+pub use neural::Genome;
+pub use process::ProcessBot;
+
+type Position = (i32, i32);
+
+pub const DIRECTIONS: [SnakeDirection; 4] = [
+    SnakeDirection::Up,
+    SnakeDirection::Down,
+    SnakeDirection::Left,
+    SnakeDirection::Right,
+];
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            GameTick,
+            steer_bots.in_set(TickSet::Input).after(apply_tick_inputs),
+        )
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            end_bots.before(setup_death_animation),
+        );
+    }
+}
+
+/// How a bot decides where to go.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum BotStrategy {
+    /// Heads straight for the closest fruit, only looking one tile ahead
+    Greedy,
+    /// Follows the shortest path to a fruit around every snake
+    Pathfinder,
+    /// Only goes for a fruit if it can still reach its own tail after eating it
+    Cautious,
+    /// Follows a loop through the whole board, taking safe shortcuts, to fill it without dying. Boards
+    /// with an odd width and height have no such loop, it plays like the cautious bot on those
+    Autopilot,
+}
+
+/// What steers a computer snake.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BotKind {
+    BuiltIn(BotStrategy),
+    /// A program started with this command, see [`ProcessBot`].
+    Process(String),
+    /// A Battlesnake server at this address, see [`BattlesnakeBot`].
+    Http(String),
+    /// A neural network saved in this file, see [`Genome`].
+    Genome(PathBuf),
+}
+
+impl BotKind {
+    /// Starts the bot for a new match, waiting at most `timeout` for every move. A bot that cannot
+    /// start crashes on its first move.
+    #[must_use]
+    pub fn start(&self, timeout: Duration) -> Box<dyn Brain> {
+        self.try_start(timeout)
+            .unwrap_or_else(|error| Box::new(Failed(error)))
+    }
+
+    /// Starts the bot for a new match, waiting at most `timeout` for every move.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the reason if the program cannot be started, the address is not supported or the
+    /// genome cannot be loaded.
+    pub fn try_start(&self, timeout: Duration) -> Result<Box<dyn Brain>, String> {
+        match self {
+            Self::BuiltIn(BotStrategy::Autopilot) => Ok(Box::<AutopilotBot>::default()),
+            Self::BuiltIn(strategy) => Ok(Box::new(*strategy)),
+            Self::Process(command) => match ProcessBot::spawn(command, timeout) {
+                Ok(bot) => Ok(Box::new(BackgroundBot::new(Box::new(bot), timeout))),
+                Err(error) => Err(format!("could not start `{command}`: {error}")),
+            },
+            Self::Http(url) => match BattlesnakeBot::new(url, timeout) {
+                Ok(bot) => Ok(Box::new(BackgroundBot::new(Box::new(bot), timeout))),
+                Err(error) => Err(error.to_string()),
+            },
+            Self::Genome(path) => match Genome::load(path) {
+                Ok(genome) => Ok(Box::new(genome)),
+                Err(error) => Err(format!("could not load {}: {error}", path.display())),
+            },
+        }
+    }
+}
+
+/// Reads a strategy name as a built-in bot, an `http://` address as a Battlesnake server, a
+/// `.genome` file as a neural network and anything else as a command. `https://` addresses are
+/// rejected, see [`BattlesnakeBot`].
+impl FromStr for BotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            Err("the bot is empty".to_owned())
+        } else if let Ok(strategy) = <BotStrategy as ValueEnum>::from_str(s, true) {
+            Ok(Self::BuiltIn(strategy))
+        } else if s.starts_with("http://") {
+            Ok(Self::Http(s.to_owned()))
+        } else if s.starts_with("https://") {
+            Err(format!(
+                "`{s}` uses https, only http:// addresses are supported"
+            ))
+        } else if Path::new(s)
+            .extension()
+            .is_some_and(|extension| extension == "genome")
+        {
+            Ok(Self::Genome(PathBuf::from(s)))
+        } else {
+            Ok(Self::Process(s.to_owned()))
+        }
+    }
+}
+
+impl Display for BotKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BuiltIn(strategy) => {
+                let name = strategy.to_possible_value().expect("no variant is skipped");
+                write!(f, "{}", name.get_name())
+            }
+            Self::Process(command) => write!(f, "{command}"),
+            Self::Http(url) => write!(f, "{url}"),
+            Self::Genome(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Picks the moves of a snake.
+pub trait Brain: Send {
+    /// Picks the next direction of the snake of `player`, `None` to keep going straight.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bot did not pick a move, its snake then keeps going straight.
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError>;
+
+    /// Starts picking the next move of the snake of `player` on the board left by the latest tick,
+    /// for bots thinking on their own thread while the game waits for the next tick.
+    fn prepare(&mut self, _state: &MatchState, _player: usize) {}
+
+    /// Like [`Brain::next_move`], but fails with [`BotError::Timeout`] right away if the bot is
+    /// still thinking about `state`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bot did not pick a move, its snake then keeps going straight.
+    fn try_next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        self.next_move(state, player)
+    }
+
+    /// Tells the bot the match is over, `state` is the final board.
+    fn end(&mut self, _state: &MatchState, _player: usize) {}
+}
+
+impl Brain for BotStrategy {
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        Ok(choose_direction(*self, state, player))
+    }
+}
+
+/// A bot that could not be started.
+struct Failed(String);
+
+impl Brain for Failed {
+    fn next_move(&mut self, _: &MatchState, _: usize) -> Result<Option<SnakeDirection>, BotError> {
+        Err(BotError::Crashed(self.0.clone()))
+    }
+}
+
+/// Why a bot did not pick a move, its snake keeps going straight.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum BotError {
+    /// The bot did not answer in time, it may still answer the next moves.
+    Timeout,
+    /// The bot answered something that is not a move.
+    InvalidReply(String),
+    /// The bot stopped working, it is not asked for moves anymore.
+    Crashed(String),
+}
+
+impl Display for BotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "did not answer in time"),
+            Self::InvalidReply(reply) => write!(f, "answered `{reply}` instead of a move"),
+            Self::Crashed(reason) => write!(f, "crashed: {reason}"),
+        }
+    }
+}
+
+/// Marks a snake steered by a bot instead of the keyboard.
+#[derive(Component)]
+pub struct Bot {
+    brain: Mutex<Box<dyn Brain>>,
+    pub report: BotReport,
+}
+
+impl Bot {
+    pub fn new(brain: Box<dyn Brain>) -> Self {
+        Self {
+            brain: Mutex::new(brain),
+            report: BotReport::default(),
+        }
+    }
+}
+
+/// How well a bot kept up during a match.
+#[derive(Clone, Default, Debug)]
+pub struct BotReport {
+    /// Number of moves the bot did not pick in time.
+    pub timeouts: u32,
+    /// Why the bot stopped steering, once it crashed.
+    pub crash: Option<String>,
+}
+
+/// Picks the next direction of the snake of `player`, `None` to keep going straight.
+#[must_use]
+pub fn choose_direction(
+    strategy: BotStrategy,
+    state: &MatchState,
+    player: usize,
+) -> Option<SnakeDirection> {
+    let snake = state
+        .snakes
+        .iter()
+        .find(|snake| snake.player == player && snake.alive)?;
+    let targets: Vec<Position> = state
+        .fruits
+        .iter()
+        .filter(|(fruit, _)| *fruit != FruitType::Poison)
+        .map(|(_, position)| *position)
+        .chain(state.bonus_fruits.iter().copied())
+        .collect();
+    match strategy {
+        BotStrategy::Greedy => greedy(state, snake, &targets),
+        BotStrategy::Pathfinder => {
+            let obstacles = obstacles(state, snake, false);
+            shortest_path(snake.body[0], &obstacles, |position| {
+                targets.contains(&position)
+            })
+            .map(|path| direction_to(snake.body[0], path[0]))
+            .or_else(|| roomiest_move(snake, &obstacles))
+        }
+        BotStrategy::Cautious => cautious(state, snake, &targets),
+        BotStrategy::Autopilot => Cycle::new(state.board)
+            .and_then(|cycle| autopilot::choose_direction(&cycle, state, snake))
+            .or_else(|| cautious(state, snake, &targets)),
+    }
+}
+
+fn greedy(state: &MatchState, snake: &SnakeState, targets: &[Position]) -> Option<SnakeDirection> {
+    let obstacles = obstacles(state, snake, false);
+    let head = snake.body[0];
+    DIRECTIONS
+        .into_iter()
+        .map(|direction| (direction, step(head, direction)))
+        .filter(|(_, next)| obstacles.is_free(*next))
+        .min_by_key(|(direction, next)| {
+            let distance = targets
+                .iter()
+                .map(|target| (target.0 - next.0).abs() + (target.1 - next.1).abs())
+                .min()
+                .unwrap_or_default();
+            (distance, *direction != snake.direction)
+        })
+        .map(|(direction, _)| direction)
+}
+
+/// Takes the shortest path to a fruit only if the tail is still reachable once the snake got
+/// there, otherwise it follows its own tail until a safe fruit shows up.
+fn cautious(
+    state: &MatchState,
+    snake: &SnakeState,
+    targets: &[Position],
+) -> Option<SnakeDirection> {
+    let obstacles = obstacles(state, snake, true);
+    let head = snake.body[0];
+    if let Some(path) = shortest_path(head, &obstacles, |position| targets.contains(&position)) {
+        if tail_reachable_after(state, snake, &path) {
+            return Some(direction_to(head, path[0]));
+        }
+    }
+    let tail_tip = *snake.body.last()?;
+    if snake.body.len() > 2 {
+        if let Some(path) = shortest_path(head, &obstacles, |position| position == tail_tip) {
+            return Some(direction_to(head, path[0]));
+        }
+    }
+    roomiest_move(snake, &obstacles)
+}
+
+/// Whether the snake could still reach the tip of its tail after following `path` and eating
+/// the fruit at its end.
+fn tail_reachable_after(state: &MatchState, snake: &SnakeState, path: &[Position]) -> bool {
+    let mut body: VecDeque<Position> = snake.body.iter().copied().collect();
+    for position in path {
+        body.push_front(*position);
+        body.pop_back();
+    }
+    let growth = state
+        .fruits
+        .iter()
+        .find(|(_, position)| Some(position) == path.last())
+        .map_or(1, |(fruit, _)| fruit.growth().max(0).unsigned_abs());
+    let Some(&tail_tip) = body.back() else {
+        return false;
+    };
+    body.resize(body.len() + growth, tail_tip);
+
+    let mut blocked = others(state, snake);
+    blocked.tiles.extend(&body);
+    blocked.tiles.remove(&tail_tip);
+    shortest_path(body[0], &blocked, |position| position == tail_tip).is_some()
+}
+
+/// The move leading to the most free tiles, for when there is no path worth taking.
+fn roomiest_move(snake: &SnakeState, obstacles: &Obstacles) -> Option<SnakeDirection> {
+    let head = snake.body[0];
+    DIRECTIONS
+        .into_iter()
+        .map(|direction| (direction, step(head, direction)))
+        .filter(|(_, next)| obstacles.is_free(*next))
+        .max_by_key(|(direction, next)| {
+            (
+                reachable_tiles(*next, obstacles),
+                *direction == snake.direction,
+            )
+        })
+        .map(|(direction, _)| direction)
+}
+
+/// Tiles the snake can not move onto on the next tick: every snake except the tips of their
+/// tails, which move away, and its own neck. The cautious bot also stays clear of the tiles
+/// the other heads could move onto.
+fn obstacles(state: &MatchState, snake: &SnakeState, cautious: bool) -> Obstacles {
+    let mut obstacles = others(state, snake);
+    obstacles.tiles.extend(&snake.body[..snake.body.len() - 1]);
+    if let Some(neck) = snake.body.get(1) {
+        obstacles.tiles.insert(*neck);
+    }
+    if cautious {
+        let heads = state
+            .snakes
+            .iter()
+            .filter(|other| other.alive && other.player != snake.player)
+            .map(|other| other.body[0]);
+        obstacles
+            .tiles
+            .extend(heads.flat_map(|head| DIRECTIONS.map(|direction| step(head, direction))));
+    }
+    obstacles
+}
+
+/// Every tile taken by the other snakes, except the tips of their tails.
+fn others(state: &MatchState, snake: &SnakeState) -> Obstacles {
+    Obstacles {
+        board: state.board,
+        tiles: state
+            .snakes
+            .iter()
+            .filter(|other| other.alive && other.player != snake.player)
+            .flat_map(|other| &other.body[..other.body.len() - 1])
+            .copied()
+            .collect(),
+    }
+}
+
+/// Tiles a snake should not move onto, besides the walls.
+struct Obstacles {
+    board: BoardSize,
+    tiles: HashSet<Position>,
+}
+
+impl Obstacles {
+    fn is_free(&self, position: Position) -> bool {
+        self.board.contains(position) && !self.tiles.contains(&position)
+    }
+}
+
+/// The tiles of the shortest path from `start` to a tile matching `goal`, without `start`.
+fn shortest_path(
+    start: Position,
+    obstacles: &Obstacles,
+    goal: impl Fn(Position) -> bool,
+) -> Option<Vec<Position>> {
+    let mut previous = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(position) = queue.pop_front() {
+        for next in DIRECTIONS.map(|direction| step(position, direction)) {
+            if next == start || previous.contains_key(&next) {
+                continue;
+            }
+            if goal(next) {
+                let mut path = vec![next];
+                let mut current = position;
+                while current != start {
+                    path.push(current);
+                    current = previous[&current];
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if obstacles.is_free(next) {
+                previous.insert(next, position);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+fn reachable_tiles(start: Position, obstacles: &Obstacles) -> usize {
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(position) = queue.pop_front() {
+        for next in DIRECTIONS.map(|direction| step(position, direction)) {
+            if obstacles.is_free(next) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    visited.len()
+}
+
+pub const fn step((x, y): Position, direction: SnakeDirection) -> Position {
+    match direction {
+        SnakeDirection::Up => (x, y + 1),
+        SnakeDirection::Down => (x, y - 1),
+        SnakeDirection::Left => (x - 1, y),
+        SnakeDirection::Right => (x + 1, y),
+    }
+}
+
+/// The direction of a tile next to `from`.
+const fn direction_to(from: Position, to: Position) -> SnakeDirection {
+    if to.1 > from.1 {
+        SnakeDirection::Up
+    } else if to.1 < from.1 {
+        SnakeDirection::Down
+    } else if to.0 < from.0 {
+        SnakeDirection::Left
+    } else {
+        SnakeDirection::Right
+    }
+}
+
+/// The name of a direction in the messages sent to bots.
+const fn direction_name(direction: SnakeDirection) -> &'static str {
+    match direction {
+        SnakeDirection::Up => "up",
+        SnakeDirection::Down => "down",
+        SnakeDirection::Left => "left",
+        SnakeDirection::Right => "right",
+    }
+}
+
+/// The answer of a bot to a board.
+#[derive(Deserialize)]
+struct MoveMessage {
+    r#move: String,
+}
+
+impl MoveMessage {
+    fn direction(&self) -> Option<SnakeDirection> {
+        DIRECTIONS
+            .into_iter()
+            .find(|direction| direction_name(*direction).eq_ignore_ascii_case(self.r#move.trim()))
+    }
+}
+
+/// Inserted by the windowed game, where the ticks do not wait for bots thinking on their own
+/// thread: a bot still thinking when the tick comes keeps going straight.
+#[derive(Resource)]
+pub struct RealTime;
+
+/// Hands the board left by the tick that just ended to every bot, to think about until the next
+/// one.
+pub fn prepare_bots(world: &mut World) {
+    let mut bot_query = world.query::<(&mut Bot, &Player)>();
+    if bot_query.iter(world).next().is_none() {
+        return;
+    }
+    let state = MatchState::from_world(world);
+    for (mut bot, player) in bot_query.iter_mut(world) {
+        if bot.report.crash.is_none() {
+            let brain = bot.brain.get_mut().unwrap_or_else(PoisonError::into_inner);
+            brain.prepare(&state, player.0);
+        }
+    }
+}
+
+fn steer_bots(world: &mut World) {
+    let mut bot_query = world.query::<(&mut Bot, &Player, &mut KeyboardDirection)>();
+    if bot_query.iter(world).next().is_none() {
+        return;
+    }
+    let state = MatchState::from_world(world);
+    let real_time = world.contains_resource::<RealTime>();
+    for (mut bot, player, mut keyboard_direction) in bot_query.iter_mut(world) {
+        if bot.report.crash.is_some() {
+            continue;
+        }
+        let brain = bot.brain.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let reply = if real_time {
+            brain.try_next_move(&state, player.0)
+        } else {
+            brain.next_move(&state, player.0)
+        };
+        match reply {
+            Ok(Some(direction)) => keyboard_direction.0.push(direction),
+            Ok(None) => {}
+            Err(error) => {
+                warn!("The bot of player {} {error}", player.0 + 1);
+                match error {
+                    BotError::Timeout => bot.report.timeouts += 1,
+                    BotError::InvalidReply(_) => {}
+                    BotError::Crashed(reason) => bot.report.crash = Some(reason),
+                }
+            }
+        }
+    }
+}
+
+/// Shows every bot the final board.
+fn end_bots(world: &mut World) {
+    let mut bot_query = world.query::<(&mut Bot, &Player)>();
+    if bot_query.iter(world).next().is_none() {
+        return;
+    }
+    let state = MatchState::from_world(world);
+    for (mut bot, player) in bot_query.iter_mut(world) {
+        let brain = bot.brain.get_mut().unwrap_or_else(PoisonError::into_inner);
+        brain.end(&state, player.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x8 board with the snake of the first player and an apple on every tile of `apples`.
+    fn board(body: Vec<Position>, direction: SnakeDirection, apples: &[Position]) -> MatchState {
+        MatchState {
+            tick: 0,
+            over: false,
+            filled: false,
+            board: BoardSize {
+                width: 8,
+                height: 8,
+            },
+            snakes: vec![SnakeState {
+                player: 0,
+                alive: true,
+                score: 0,
+                direction,
+                body,
+            }],
+            fruits: apples
+                .iter()
+                .map(|position| (FruitType::Apple, *position))
+                .collect(),
+            bonus_fruits: Vec::new(),
+            power_ups: Vec::new(),
+        }
+    }
+
+    fn walls(tiles: impl IntoIterator<Item = Position>) -> Obstacles {
+        Obstacles {
+            board: BoardSize {
+                width: 8,
+                height: 8,
+            },
+            tiles: tiles.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn the_shortest_path_goes_around_obstacles() {
+        let obstacles = walls((2..9).map(|y| (5, y)));
+        let path = shortest_path((3, 3), &obstacles, |position| position == (7, 3)).unwrap();
+        // Up to the gap at the top, across and back down.
+        assert_eq!(path.len(), 6 + 4 + 6);
+        assert_eq!(path.last(), Some(&(7, 3)));
+        let mut previous = (3, 3);
+        for position in path {
+            assert!(obstacles.is_free(position));
+            assert_eq!(
+                (position.0 - previous.0).abs() + (position.1 - previous.1).abs(),
+                1
+            );
+            previous = position;
+        }
+    }
+
+    #[test]
+    fn there_is_no_path_to_a_walled_off_tile() {
+        let obstacles = walls([(6, 3), (8, 3), (7, 2), (7, 4)]);
+        assert_eq!(
+            shortest_path((3, 3), &obstacles, |position| position == (7, 3)),
+            None
+        );
+    }
+
+    #[test]
+    fn bots_head_for_the_fruit_next_to_them() {
+        let state = board(
+            vec![(5, 5), (4, 5), (3, 5)],
+            SnakeDirection::Right,
+            &[(5, 6)],
+        );
+        for strategy in [
+            BotStrategy::Greedy,
+            BotStrategy::Pathfinder,
+            BotStrategy::Cautious,
+        ] {
+            assert_eq!(
+                choose_direction(strategy, &state, 0),
+                Some(SnakeDirection::Up),
+                "{strategy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bots_stay_clear_of_walls_and_their_own_body() {
+        let state = board(vec![(9, 9), (9, 8), (9, 7)], SnakeDirection::Up, &[]);
+        for strategy in [
+            BotStrategy::Greedy,
+            BotStrategy::Pathfinder,
+            BotStrategy::Cautious,
+        ] {
+            assert_eq!(
+                choose_direction(strategy, &state, 0),
+                Some(SnakeDirection::Left),
+                "{strategy:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn the_cautious_bot_leaves_fruits_it_could_not_get_away_from() {
+        // The snake walls off the first column, which it would fill up getting to the apple at
+        // its bottom, with its tail out of reach.
+        let body: Vec<Position> = (2..10)
+            .rev()
+            .map(|y| (3, y))
+            .chain((4..10).map(|x| (x, 2)))
+            .chain((3..7).map(|y| (9, y)))
+            .collect();
+        let state = board(body, SnakeDirection::Up, &[(2, 2)]);
+        assert_eq!(
+            choose_direction(BotStrategy::Pathfinder, &state, 0),
+            Some(SnakeDirection::Left)
+        );
+        assert_eq!(
+            choose_direction(BotStrategy::Cautious, &state, 0),
+            Some(SnakeDirection::Right)
+        );
+    }
+
+    #[test]
+    fn bots_are_told_apart_by_how_they_are_written() {
+        assert_eq!(
+            "Cautious".parse(),
+            Ok(BotKind::BuiltIn(BotStrategy::Cautious))
+        );
+        assert_eq!(
+            "http://localhost:8000".parse(),
+            Ok(BotKind::Http("http://localhost:8000".to_owned()))
+        );
+        assert_eq!(
+            "best.genome".parse(),
+            Ok(BotKind::Genome(PathBuf::from("best.genome")))
+        );
+        assert_eq!(
+            "python3 bot.py".parse(),
+            Ok(BotKind::Process("python3 bot.py".to_owned()))
+        );
+        assert!("https://localhost:8000".parse::<BotKind>().is_err());
+        assert!(" ".parse::<BotKind>().is_err());
+    }
+}
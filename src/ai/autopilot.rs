@@ -0,0 +1,338 @@
+//! A bot that never dies by following a Hamiltonian cycle, a loop through every tile of the
+//! board.
+//!
+//! Following the cycle alone fills the board, but takes a whole lap for every fruit. While the
+//! snake is shorter than half the board it also takes shortcuts towards the fruit, as long as the
+//! tile it jumps to still comes before the tip of its tail on the cycle. The body then always
+//! stays in cycle order, so the way ahead is free.
+//!
+//! Boards with an odd width and height have no such cycle, the cautious bot plays those instead.
+
+use bevy::prelude::*;
+
+use super::{
+    direction_to, obstacles, step, Bot, BotError, BotStrategy, Brain, Position, DIRECTIONS,
+};
+use crate::{
+    headless::{MatchState, SnakeState},
+    netplay::Session,
+    BoardSize, FruitType, GameState, Player, SnakeDirection,
+};
+
+/// Room left between the head and the tip of the tail for the segments eating a fruit adds.
+const GROWTH_MARGIN: usize = 4;
+
+/// Lets the first player hand their snake over to the autopilot with `P`, and take it back.
+pub struct AutopilotPlugin {
+    /// Whether the autopilot steers from the start, for an attract mode.
+    pub enabled: bool,
+}
+
+impl Plugin for AutopilotPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Autopilot(self.enabled))
+            .add_systems(Startup, setup_autopilot_hud)
+            .add_systems(
+                Update,
+                (
+                    toggle_autopilot,
+                    steer_first_snake.run_if(in_state(GameState::Playing)),
+                    update_autopilot_hud,
+                )
+                    .chain()
+                    .run_if(not(resource_exists::<Session>())),
+            );
+    }
+}
+
+/// Whether the autopilot steers the snake of the first player.
+#[derive(Resource)]
+struct Autopilot(bool);
+
+#[derive(Component)]
+struct AutopilotText;
+
+/// The autopilot as a bot, building the cycle once for the board it plays on.
+#[derive(Default)]
+pub(super) struct AutopilotBot {
+    board: Option<BoardSize>,
+    cycle: Option<Cycle>,
+}
+
+impl AutopilotBot {
+    fn cycle(&mut self, board: BoardSize) -> Option<&Cycle> {
+        if self.board != Some(board) {
+            self.board = Some(board);
+            self.cycle = Cycle::new(board);
+        }
+        self.cycle.as_ref()
+    }
+}
+
+impl Brain for AutopilotBot {
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        let Some(snake) = state
+            .snakes
+            .iter()
+            .find(|snake| snake.player == player && snake.alive)
+        else {
+            return Ok(None);
+        };
+        Ok(self
+            .cycle(state.board)
+            .and_then(|cycle| choose_direction(cycle, state, snake))
+            .or_else(|| super::choose_direction(BotStrategy::Cautious, state, player)))
+    }
+}
+
+/// Picks the next direction of `snake` along `cycle`, `None` if every way is blocked.
+pub(super) fn choose_direction(
+    cycle: &Cycle,
+    state: &MatchState,
+    snake: &SnakeState,
+) -> Option<SnakeDirection> {
+    let head = snake.body[0];
+    let length = cycle.order.len();
+    // The snake does not start on the cycle, so pick the way round it is already heading.
+    let reversed = snake
+        .body
+        .get(1)
+        .is_some_and(|neck| cycle.index(*neck) == (cycle.index(head) + 1) % length);
+    let distance = |position: Position| {
+        let (from, to) = (cycle.index(head), cycle.index(position));
+        if reversed {
+            (from + length - to) % length
+        } else {
+            (to + length - from) % length
+        }
+    };
+    let next = if reversed {
+        cycle.order[(cycle.index(head) + length - 1) % length]
+    } else {
+        cycle.order[(cycle.index(head) + 1) % length]
+    };
+
+    let target = state
+        .fruits
+        .iter()
+        .filter(|(fruit, _)| *fruit != FruitType::Poison)
+        .map(|(_, position)| *position)
+        .chain(state.bonus_fruits.iter().copied())
+        .min_by_key(|position| distance(*position));
+    let Some(target) = target else {
+        return Some(direction_to(head, next));
+    };
+    if snake.body.len() * 2 > length {
+        return Some(direction_to(head, next));
+    }
+    let tail = match snake.body.last() {
+        Some(tail) if snake.body.len() > 1 => distance(*tail),
+        _ => length,
+    };
+    let obstacles = obstacles(state, snake, false);
+    DIRECTIONS
+        .into_iter()
+        .map(|direction| step(head, direction))
+        .filter(|position| {
+            obstacles.is_free(*position)
+                && distance(*position) <= distance(target)
+                && distance(*position) + GROWTH_MARGIN < tail
+        })
+        .max_by_key(|position| distance(*position))
+        .or_else(|| obstacles.is_free(next).then_some(next))
+        .map(|position| direction_to(head, position))
+}
+
+/// The tiles of the board in the order the cycle goes through them.
+pub(super) struct Cycle {
+    board: BoardSize,
+    order: Vec<Position>,
+    /// Where every tile is in `order`, column by column.
+    indices: Vec<usize>,
+}
+
+impl Cycle {
+    /// Snakes through every column but the first one, row by row, and comes back down the first
+    /// column. That needs an even number of rows, so boards with an odd height are turned around.
+    pub(super) fn new(board: BoardSize) -> Option<Self> {
+        let (transposed, width, height) = if board.height % 2 == 0 {
+            (false, board.width, board.height)
+        } else if board.width % 2 == 0 {
+            (true, board.height, board.width)
+        } else {
+            return None;
+        };
+        let rows = (0..height).flat_map(|y| {
+            let columns: Box<dyn Iterator<Item = i32>> = if y % 2 == 0 {
+                Box::new(1..width)
+            } else {
+                Box::new((1..width).rev())
+            };
+            columns.map(move |x| (x, y))
+        });
+        let back = (0..height).rev().map(|y| (0, y));
+        let order: Vec<Position> = rows
+            .chain(back)
+            .map(|(x, y)| if transposed { (y, x) } else { (x, y) })
+            .map(|(x, y)| (x + 2, y + 2))
+            .collect();
+        let mut indices = vec![0; order.len()];
+        for (index, position) in order.iter().enumerate() {
+            indices[tile(board, *position)] = index;
+        }
+        Some(Self {
+            board,
+            order,
+            indices,
+        })
+    }
+
+    fn index(&self, position: Position) -> usize {
+        self.indices[tile(self.board, position)]
+    }
+}
+
+/// Index of a tile inside the walls, column by column.
+const fn tile(board: BoardSize, (x, y): Position) -> usize {
+    ((x - 2) * board.height + (y - 2)).unsigned_abs() as usize
+}
+
+fn setup_autopilot_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            right: Val::Px(8.0),
+            ..Default::default()
+        }),
+        AutopilotText,
+    ));
+}
+
+fn toggle_autopilot(keyboard_input: Res<Input<KeyCode>>, mut autopilot: ResMut<Autopilot>) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        autopilot.0 = !autopilot.0;
+    }
+}
+
+/// Hands the snake of the first player to the autopilot while it is enabled, the snakes are
+/// spawned again for every match.
+fn steer_first_snake(
+    mut commands: Commands,
+    autopilot: Res<Autopilot>,
+    snake_query: Query<(Entity, &Player, Has<Bot>)>,
+) {
+    for (entity, _, bot) in snake_query.iter().filter(|(_, player, _)| player.0 == 0) {
+        if autopilot.0 && !bot {
+            commands
+                .entity(entity)
+                .insert(Bot::new(Box::<AutopilotBot>::default()));
+        } else if !autopilot.0 && bot {
+            commands.entity(entity).remove::<Bot>();
+        }
+    }
+}
+
+fn update_autopilot_hud(
+    autopilot: Res<Autopilot>,
+    mut text_query: Query<&mut Text, With<AutopilotText>>,
+) {
+    if !autopilot.is_changed() {
+        return;
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = if autopilot.0 {
+            "Autopilot, press P to take over".to_owned()
+        } else {
+            String::new()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ai::BotKind,
+        headless::{Match, MatchConfig},
+    };
+
+    #[test]
+    fn the_cycle_goes_through_every_tile_once() {
+        for (width, height) in [(8, 8), (8, 9), (9, 8), (2, 2)] {
+            let board = BoardSize { width, height };
+            let cycle = Cycle::new(board).unwrap();
+            let mut visited: Vec<Position> = cycle.order.clone();
+            visited.sort_unstable();
+            assert!(visited.into_iter().eq(board.positions()), "{board}");
+            for (index, position) in cycle.order.iter().enumerate() {
+                let next = cycle.order[(index + 1) % cycle.order.len()];
+                assert_eq!(
+                    (next.0 - position.0).abs() + (next.1 - position.1).abs(),
+                    1,
+                    "{board}"
+                );
+                assert_eq!(cycle.index(*position), index);
+            }
+        }
+        assert!(Cycle::new(BoardSize {
+            width: 9,
+            height: 9
+        })
+        .is_none());
+    }
+
+    fn bot_match(strategy: BotStrategy, board: BoardSize) -> Match {
+        Match::new(MatchConfig {
+            players: 0,
+            bots: vec![BotKind::BuiltIn(strategy)],
+            board,
+            ..MatchConfig::default()
+        })
+    }
+
+    #[test]
+    fn the_autopilot_fills_the_board() {
+        for (width, height) in [(8, 8), (10, 8), (8, 9)] {
+            let board = BoardSize { width, height };
+            let mut game = bot_match(BotStrategy::Autopilot, board);
+            while game.step() {}
+            let state = game.state();
+            assert!(state.filled, "died after {} ticks on {board}", state.tick);
+            assert_eq!(state.winner(), Some(0));
+        }
+    }
+
+    #[test]
+    fn the_autopilot_plays_like_the_cautious_bot_without_a_cycle() {
+        let board = BoardSize {
+            width: 9,
+            height: 9,
+        };
+        let mut autopilot = bot_match(BotStrategy::Autopilot, board);
+        let mut cautious = bot_match(BotStrategy::Cautious, board);
+        loop {
+            let playing = autopilot.step();
+            assert_eq!(cautious.step(), playing);
+            assert_eq!(
+                format!("{:?}", autopilot.state()),
+                format!("{:?}", cautious.state())
+            );
+            if !playing {
+                break;
+            }
+        }
+    }
+}
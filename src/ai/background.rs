@@ -0,0 +1,176 @@
+//! Bots answering on their own thread, so a slow program or server never holds up the game.
+//!
+//! The game hands every bot the board as soon as a tick is over, see [`Brain::prepare`], and the
+//! bot thinks about it while the game waits for the next tick. At that tick the game running in
+//! real time only picks up the answer if it is there, while a headless match waits for it until
+//! the timeout, like it would for a bot answering on the game thread.
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{BotError, Brain};
+use crate::{headless::MatchState, SnakeDirection};
+
+type Reply = Result<Option<SnakeDirection>, BotError>;
+
+enum Request {
+    Move(MatchState, usize),
+    End(MatchState, usize),
+}
+
+pub struct BackgroundBot {
+    requests: Sender<Request>,
+    /// Answers of the bot, with the tick of the board they are for.
+    replies: Receiver<(u64, Reply)>,
+    /// The tick of the latest board sent to the bot, and when its answer is due.
+    asked: Option<(u64, Instant)>,
+    timeout: Duration,
+}
+
+impl BackgroundBot {
+    /// Moves `brain` to a thread of its own, giving it `timeout` to answer every board.
+    #[must_use]
+    pub fn new(brain: Box<dyn Brain>, timeout: Duration) -> Self {
+        let (requests, boards) = mpsc::channel();
+        let (answers, replies) = mpsc::channel();
+        thread::spawn(move || think(brain, &boards, &answers));
+        Self {
+            requests,
+            replies,
+            asked: None,
+            timeout,
+        }
+    }
+
+    /// Sends the board to the bot, unless it already has it.
+    fn ask(&mut self, state: &MatchState, player: usize) {
+        if self.asked.is_some_and(|(tick, _)| tick == state.tick) {
+            return;
+        }
+        // A bot thread that stopped is reported when the answer is read.
+        let _ = self.requests.send(Request::Move(state.clone(), player));
+        self.asked = Some((state.tick, Instant::now() + self.timeout));
+    }
+
+    /// Waits for the answer to the board of `tick` until `deadline`, throwing away the answers to
+    /// boards the game gave up on.
+    fn answer(&self, tick: u64, deadline: Instant) -> Reply {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(timeout) {
+                Ok((answered, reply)) if answered == tick => return reply,
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return Err(BotError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(BotError::Crashed(
+                        "the thread of the bot stopped".to_owned(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Brain for BackgroundBot {
+    fn prepare(&mut self, state: &MatchState, player: usize) {
+        self.ask(state, player);
+    }
+
+    fn next_move(&mut self, state: &MatchState, player: usize) -> Reply {
+        self.ask(state, player);
+        let deadline = self
+            .asked
+            .map_or_else(Instant::now, |(_, deadline)| deadline);
+        self.answer(state.tick, deadline)
+    }
+
+    fn try_next_move(&mut self, state: &MatchState, player: usize) -> Reply {
+        self.ask(state, player);
+        self.answer(state.tick, Instant::now())
+    }
+
+    fn end(&mut self, state: &MatchState, player: usize) {
+        let _ = self.requests.send(Request::End(state.clone(), player));
+    }
+}
+
+/// Answers the boards sent by the game until it drops the bot.
+fn think(mut brain: Box<dyn Brain>, boards: &Receiver<Request>, answers: &Sender<(u64, Reply)>) {
+    while let Ok(mut request) = boards.recv() {
+        // Only the latest board is worth answering, the game gave up on the others already.
+        while let Ok(newer) = boards.try_recv() {
+            request = newer;
+        }
+        match request {
+            Request::Move(state, player) => {
+                if answers
+                    .send((state.tick, brain.next_move(&state, player)))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Request::End(state, player) => brain.end(&state, player),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BoardSize;
+
+    /// Takes its time, then always turns up.
+    struct Slow(Duration);
+
+    impl Brain for Slow {
+        fn next_move(&mut self, _: &MatchState, _: usize) -> Reply {
+            thread::sleep(self.0);
+            Ok(Some(SnakeDirection::Up))
+        }
+    }
+
+    fn board(tick: u64) -> MatchState {
+        MatchState {
+            tick,
+            over: false,
+            filled: false,
+            board: BoardSize::default(),
+            snakes: Vec::new(),
+            fruits: Vec::new(),
+            bonus_fruits: Vec::new(),
+            power_ups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_game_only_waits_for_answers_outside_real_time() {
+        let mut bot = BackgroundBot::new(
+            Box::new(Slow(Duration::from_millis(20))),
+            Duration::from_secs(5),
+        );
+        bot.prepare(&board(0), 0);
+        assert_eq!(bot.try_next_move(&board(0), 0), Err(BotError::Timeout));
+
+        bot.prepare(&board(1), 0);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(
+            bot.try_next_move(&board(1), 0),
+            Ok(Some(SnakeDirection::Up))
+        );
+
+        assert_eq!(bot.next_move(&board(2), 0), Ok(Some(SnakeDirection::Up)));
+    }
+
+    #[test]
+    fn late_answers_time_out() {
+        let mut bot = BackgroundBot::new(
+            Box::new(Slow(Duration::from_millis(500))),
+            Duration::from_millis(20),
+        );
+        assert_eq!(bot.next_move(&board(0), 0), Err(BotError::Timeout));
+    }
+}
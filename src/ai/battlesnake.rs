@@ -0,0 +1,534 @@
+//! Bots served over HTTP with the Battlesnake API, so the many bots written for it can join a
+//! match.
+//!
+//! The game posts the board to `/start` before the first move, to `/move` every tick and to
+//! `/end` once the match is over, and reads `{"move":"up"}` back from `/move`. Battlesnake boards
+//! have no walls inside them, so every tile is moved down and left by 2 to start at `(0, 0)` in the
+//! bottom left corner. Snakes never starve here, their health is always 100. Poison is sent as
+//! hazards, the other fruits as food.
+//!
+//! Only plain `http://` addresses are supported, which is all a bot running on the same machine or
+//! network needs.
+
+use std::{
+    borrow::Cow,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use super::{BotError, Brain, MoveMessage, Position};
+use crate::{headless::MatchState, headless::SnakeState, FruitType, SnakeDirection};
+
+/// Tells the matches played by this process apart on a bot server.
+static GAMES_STARTED: AtomicU64 = AtomicU64::new(0);
+
+pub struct BattlesnakeBot {
+    /// Host and port of the server, like `localhost:8000`.
+    host: String,
+    /// Path the endpoints are under, without a trailing slash.
+    path: String,
+    game_id: String,
+    started: bool,
+    timeout: Duration,
+}
+
+impl BattlesnakeBot {
+    /// Plays against the bot served at `url`, like `http://localhost:8000`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `url` is not a plain `http://` address.
+    pub fn new(url: &str, timeout: Duration) -> io::Result<Self> {
+        let address = url.strip_prefix("http://").ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("`{url}` is not an http:// address"),
+            )
+        })?;
+        let (host, path) = address.split_at(address.find('/').unwrap_or(address.len()));
+        if host.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("`{url}` has no host"),
+            ));
+        }
+        let host = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{host}:80")
+        };
+        Ok(Self {
+            host,
+            path: path.trim_end_matches('/').to_owned(),
+            game_id: format!(
+                "{}-{}",
+                process::id(),
+                GAMES_STARTED.fetch_add(1, Ordering::Relaxed)
+            ),
+            started: false,
+            timeout,
+        })
+    }
+
+    /// Posts the board to `endpoint` and returns the body of the answer, if it comes by `deadline`.
+    fn post(
+        &self,
+        endpoint: &str,
+        state: &MatchState,
+        player: usize,
+        deadline: Instant,
+    ) -> Result<String, BotError> {
+        let request = GameRequest::new(state, player, &self.game_id, self.timeout)
+            .ok_or_else(|| BotError::Crashed("the snake is not on the board".to_owned()))?;
+        let body = serde_json::to_string(&request)
+            .map_err(|error| BotError::Crashed(error.to_string()))?;
+        let request = format!(
+            "POST {}{endpoint} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.path,
+            self.host,
+            body.len(),
+        );
+        let response = self
+            .exchange(request.as_bytes(), deadline)
+            .map_err(|error| match error.kind() {
+                ErrorKind::TimedOut | ErrorKind::WouldBlock => BotError::Timeout,
+                _ => BotError::Crashed(format!("{endpoint} failed: {error}")),
+            })?;
+        // The chunk sizes count bytes, so the body is only read as text once it is joined.
+        let (head, body) =
+            split_response(&response).unwrap_or_else(|| (String::from_utf8_lossy(&response), &[]));
+        let status = head.lines().next().unwrap_or_default();
+        if !status
+            .split_whitespace()
+            .nth(1)
+            .is_some_and(|code| code.starts_with('2'))
+        {
+            return Err(BotError::InvalidReply(status.to_owned()));
+        }
+        Ok(if is_chunked(&head) {
+            String::from_utf8_lossy(&dechunk(body).0).into_owned()
+        } else {
+            String::from_utf8_lossy(body).into_owned()
+        })
+    }
+
+    /// Sends `request` and reads the answer, until its whole body arrived or the server closes
+    /// the connection.
+    fn exchange(&self, request: &[u8], deadline: Instant) -> io::Result<Vec<u8>> {
+        let remaining = || {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                Err(io::Error::from(ErrorKind::TimedOut))
+            } else {
+                Ok(remaining)
+            }
+        };
+        let address = self
+            .host
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "the host has no address"))?;
+        let mut stream = TcpStream::connect_timeout(&address, remaining()?)?;
+        stream.set_write_timeout(Some(remaining()?))?;
+        stream.write_all(request)?;
+        let mut response = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            stream.set_read_timeout(Some(remaining()?))?;
+            match stream.read(&mut buffer)? {
+                0 => return Ok(response),
+                length => response.extend_from_slice(&buffer[..length]),
+            }
+            if is_complete(&response) {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+impl Brain for BattlesnakeBot {
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        if !state
+            .snakes
+            .iter()
+            .any(|snake| snake.player == player && snake.alive)
+        {
+            return Ok(None);
+        }
+        // The first move waits for `/start` too, so both share the time the bot has for a move.
+        let deadline = Instant::now() + self.timeout;
+        if !self.started {
+            self.started = true;
+            self.post("/start", state, player, deadline)?;
+        }
+        let reply = self.post("/move", state, player, deadline)?;
+        serde_json::from_str::<MoveMessage>(&reply)
+            .ok()
+            .and_then(|reply| reply.direction())
+            .map(Some)
+            .ok_or(BotError::InvalidReply(reply))
+    }
+
+    fn end(&mut self, state: &MatchState, player: usize) {
+        if self.started {
+            let _ = self.post("/end", state, player, Instant::now() + self.timeout);
+        }
+    }
+}
+
+/// Splits a response into its head and body, `None` until the whole head arrived.
+fn split_response(response: &[u8]) -> Option<(Cow<'_, str>, &[u8])> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+    Some((
+        String::from_utf8_lossy(&response[..end]),
+        &response[end + 4..],
+    ))
+}
+
+/// The value of the header `name` in `head`, ignoring case.
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+fn is_chunked(head: &str) -> bool {
+    header(head, "Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+}
+
+/// Whether the whole body of `response` arrived, as told by its `Content-Length` or final chunk.
+/// Without either the body ends when the server closes the connection.
+fn is_complete(response: &[u8]) -> bool {
+    let Some((head, body)) = split_response(response) else {
+        return false;
+    };
+    if is_chunked(&head) {
+        dechunk(body).1
+    } else {
+        header(&head, "Content-Length")
+            .and_then(|length| length.parse().ok())
+            .is_some_and(|length: usize| body.len() >= length)
+    }
+}
+
+/// Joins the chunks of a body sent with `Transfer-Encoding: chunked`, along with whether the final
+/// chunk arrived.
+fn dechunk(mut body: &[u8]) -> (Vec<u8>, bool) {
+    let mut joined = Vec::new();
+    while let Some(line_end) = body.windows(2).position(|window| window == b"\r\n") {
+        let line = String::from_utf8_lossy(&body[..line_end]);
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let Ok(size) = usize::from_str_radix(size, 16) else {
+            break;
+        };
+        let rest = &body[line_end + 2..];
+        if size == 0 {
+            return (joined, true);
+        }
+        if rest.len() < size {
+            break;
+        }
+        joined.extend_from_slice(&rest[..size]);
+        let rest = &rest[size..];
+        body = rest.strip_prefix(b"\r\n").unwrap_or(rest);
+    }
+    (joined, false)
+}
+
+#[derive(Serialize)]
+struct GameRequest {
+    game: Game,
+    turn: u64,
+    board: Board,
+    you: Battlesnake,
+}
+
+impl GameRequest {
+    fn new(state: &MatchState, player: usize, game_id: &str, timeout: Duration) -> Option<Self> {
+        let point = |(x, y): Position| Point { x: x - 2, y: y - 2 };
+        let snake = |snake: &SnakeState| Battlesnake {
+            id: format!("player-{}", snake.player + 1),
+            name: format!("Player {}", snake.player + 1),
+            health: 100,
+            body: snake.body.iter().copied().map(point).collect(),
+            latency: "0",
+            head: point(snake.body[0]),
+            length: snake.body.len(),
+            shout: "",
+            squad: "",
+            customizations: Customizations {
+                color: "#888888",
+                head: "default",
+                tail: "default",
+            },
+        };
+        let you = snake(state.snakes.iter().find(|snake| snake.player == player)?);
+        Some(Self {
+            game: Game {
+                id: game_id.to_owned(),
+                ruleset: Ruleset {
+                    name: if state.snakes.len() == 1 {
+                        "solo"
+                    } else {
+                        "standard"
+                    },
+                    version: env!("CARGO_PKG_VERSION"),
+                    settings: Settings {
+                        food_spawn_chance: 0,
+                        minimum_food: 1,
+                        hazard_damage_per_turn: 0,
+                    },
+                },
+                map: "standard",
+                timeout: u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX),
+                source: "custom",
+            },
+            turn: state.tick,
+            board: Board {
+                width: state.board.width,
+                height: state.board.height,
+                food: state
+                    .fruits
+                    .iter()
+                    .filter(|(fruit, _)| *fruit != FruitType::Poison)
+                    .map(|(_, position)| *position)
+                    .chain(state.bonus_fruits.iter().copied())
+                    .map(point)
+                    .collect(),
+                hazards: state
+                    .fruits
+                    .iter()
+                    .filter(|(fruit, _)| *fruit == FruitType::Poison)
+                    .map(|(_, position)| point(*position))
+                    .collect(),
+                snakes: state
+                    .snakes
+                    .iter()
+                    .filter(|snake| snake.alive)
+                    .map(snake)
+                    .collect(),
+            },
+            you,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Game {
+    id: String,
+    ruleset: Ruleset,
+    map: &'static str,
+    /// Milliseconds the bot has to answer.
+    timeout: u64,
+    source: &'static str,
+}
+
+#[derive(Serialize)]
+struct Ruleset {
+    name: &'static str,
+    version: &'static str,
+    settings: Settings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Settings {
+    food_spawn_chance: u32,
+    minimum_food: u32,
+    hazard_damage_per_turn: u32,
+}
+
+#[derive(Serialize)]
+struct Board {
+    width: i32,
+    height: i32,
+    food: Vec<Point>,
+    hazards: Vec<Point>,
+    snakes: Vec<Battlesnake>,
+}
+
+#[derive(Serialize)]
+struct Battlesnake {
+    id: String,
+    name: String,
+    health: u32,
+    body: Vec<Point>,
+    latency: &'static str,
+    head: Point,
+    length: usize,
+    shout: &'static str,
+    squad: &'static str,
+    customizations: Customizations,
+}
+
+#[derive(Serialize)]
+struct Customizations {
+    color: &'static str,
+    head: &'static str,
+    tail: &'static str,
+}
+
+#[derive(Serialize, Clone, Copy)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, sync::mpsc, thread};
+
+    use super::*;
+    use crate::BoardSize;
+
+    fn state() -> MatchState {
+        MatchState {
+            tick: 0,
+            over: false,
+            filled: false,
+            board: BoardSize::default(),
+            snakes: vec![SnakeState {
+                player: 0,
+                alive: true,
+                score: 0,
+                direction: SnakeDirection::Right,
+                body: vec![(5, 5), (4, 5), (3, 5)],
+            }],
+            fruits: vec![(FruitType::Apple, (8, 8))],
+            bonus_fruits: Vec::new(),
+            power_ups: Vec::new(),
+        }
+    }
+
+    /// Reads a whole request, headers and body, and returns its first line.
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let length = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..length]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |length| length.parse().unwrap());
+                if body.len() >= content_length {
+                    return head.lines().next().unwrap().to_owned();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dechunk_keeps_characters_split_between_chunks() {
+        let body = "{\"move\":\"left\",\"shout\":\"h\u{e9}h\u{e9}\"}".as_bytes();
+        // The first chunk ends in the middle of the two bytes of the first é.
+        let split = body.iter().position(|byte| *byte == 0xc3).unwrap() + 1;
+        let mut chunked = Vec::new();
+        for chunk in [&body[..split], &body[split..]] {
+            chunked.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            chunked.extend_from_slice(chunk);
+            chunked.extend_from_slice(b"\r\n");
+        }
+        chunked.extend_from_slice(b"0\r\n\r\n");
+        assert_eq!(dechunk(&chunked), (body.to_vec(), true));
+        assert!(!dechunk(&chunked[..chunked.len() - 5]).1);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in [
+                b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                [
+                    &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"[..],
+                    &chunked,
+                ]
+                .concat(),
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(read_request(&mut stream));
+                stream.write_all(&response).unwrap();
+            }
+            requests
+        });
+
+        let mut bot =
+            BattlesnakeBot::new(&format!("http://{address}"), Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            bot.next_move(&state(), 0).unwrap(),
+            Some(SnakeDirection::Left)
+        );
+        assert_eq!(
+            server.join().unwrap(),
+            ["POST /start HTTP/1.1", "POST /move HTTP/1.1"]
+        );
+    }
+
+    #[test]
+    fn the_first_move_shares_its_time_with_start() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for _ in 0..2 {
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+                read_request(&mut stream);
+                thread::sleep(Duration::from_millis(400));
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 13\r\n\r\n{\"move\":\"up\"}");
+            }
+        });
+
+        let mut bot =
+            BattlesnakeBot::new(&format!("http://{address}"), Duration::from_millis(600)).unwrap();
+        assert_eq!(bot.next_move(&state(), 0), Err(BotError::Timeout));
+    }
+
+    #[test]
+    fn answers_end_without_the_connection_closing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (done, finished) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let mut streams = Vec::new();
+            for response in [
+                &b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n"[..],
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nd\r\n{\"move\":\"up\"}\r\n0\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                read_request(&mut stream);
+                stream.write_all(response).unwrap();
+                streams.push(stream);
+            }
+            // Keeps both connections open until the bot moved.
+            finished.recv().unwrap();
+            streams
+        });
+
+        let mut bot =
+            BattlesnakeBot::new(&format!("http://{address}"), Duration::from_secs(5)).unwrap();
+        let started = Instant::now();
+        let direction = bot.next_move(&state(), 0);
+        done.send(()).unwrap();
+        assert_eq!(server.join().unwrap().len(), 2);
+        assert_eq!(direction, Ok(Some(SnakeDirection::Up)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,232 @@
+//! Bots steered by a small neural network, evolved by `snake-game-train`.
+//!
+//! The network sees the board like an agent of the [`Environment`](crate::environment) with
+//! [`Encoding::Features`](crate::environment::Encoding::Features), runs it through one hidden
+//! layer and takes the [`Action`] with the highest output. A genome is all the weights of the
+//! network, saved as JSON.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind},
+    path::Path,
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{BotError, Brain};
+use crate::{
+    environment::{features, Action, FEATURE_COUNT},
+    headless::MatchState,
+    SnakeDirection,
+};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Genome {
+    /// Number of neurons in the hidden layer.
+    pub hidden: usize,
+    /// Weights of every hidden neuron then of every output, each followed by its bias.
+    pub weights: Vec<f32>,
+}
+
+impl Genome {
+    /// A network with `hidden` neurons and random weights.
+    pub fn random(hidden: usize, rng: &mut impl Rng) -> Self {
+        Self {
+            hidden,
+            weights: (0..weight_count(hidden))
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect(),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read or does not hold a genome.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let genome: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if genome.weights.len() != weight_count(genome.hidden) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} weights do not fit a network with {} hidden neurons",
+                    genome.weights.len(),
+                    genome.hidden
+                ),
+            ));
+        }
+        Ok(genome)
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the file cannot be written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Picks the action with the highest output for `features`.
+    #[must_use]
+    pub fn decide(&self, features: &[f32]) -> Action {
+        let (hidden_weights, output_weights) =
+            self.weights.split_at(self.hidden * (FEATURE_COUNT + 1));
+        let hidden: Vec<f32> = hidden_weights
+            .chunks(FEATURE_COUNT + 1)
+            .map(|neuron| neuron_output(neuron, features).tanh())
+            .collect();
+        output_weights
+            .chunks(self.hidden + 1)
+            .map(|neuron| neuron_output(neuron, &hidden))
+            .zip(Action::ALL)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map_or(Action::Straight, |(_, action)| action)
+    }
+
+    /// A child taking every weight from either parent, both must have the same hidden layer.
+    #[must_use]
+    pub fn crossover(&self, other: &Self, rng: &mut impl Rng) -> Self {
+        Self {
+            hidden: self.hidden,
+            weights: self
+                .weights
+                .iter()
+                .zip(&other.weights)
+                .map(|(mine, theirs)| if rng.gen_bool(0.5) { *mine } else { *theirs })
+                .collect(),
+        }
+    }
+
+    /// Nudges every weight by up to `strength` with a chance of `rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not between 0 and 1, or `strength` is not above 0.
+    pub fn mutate(&mut self, rate: f64, strength: f32, rng: &mut impl Rng) {
+        for weight in &mut self.weights {
+            if rng.gen_bool(rate) {
+                *weight += rng.gen_range(-strength..strength);
+            }
+        }
+    }
+}
+
+impl Brain for Genome {
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        Ok(state
+            .snakes
+            .iter()
+            .find(|snake| snake.player == player && snake.alive)
+            .map(|snake| {
+                self.decide(&features(state, snake))
+                    .direction(snake.direction)
+            }))
+    }
+}
+
+const fn weight_count(hidden: usize) -> usize {
+    hidden * (FEATURE_COUNT + 1) + Action::ALL.len() * (hidden + 1)
+}
+
+/// The weighted sum of `inputs`, with the bias as the last weight.
+fn neuron_output(weights: &[f32], inputs: &[f32]) -> f32 {
+    let (bias, weights) = weights.split_last().expect("every neuron has a bias");
+    weights
+        .iter()
+        .zip(inputs)
+        .map(|(weight, input)| weight * input)
+        .sum::<f32>()
+        + bias
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// A network whose only hidden neuron copies the first feature, which the outputs go straight
+    /// with, left against and right ignore.
+    fn follower() -> Genome {
+        let mut weights = vec![0.0; weight_count(1)];
+        weights[0] = 1.0;
+        let outputs = FEATURE_COUNT + 1;
+        weights[outputs] = 1.0;
+        weights[outputs + 2] = -1.0;
+        Genome { hidden: 1, weights }
+    }
+
+    #[test]
+    fn the_highest_output_decides() {
+        let genome = follower();
+        let mut features = [0.0; FEATURE_COUNT];
+        features[0] = 1.0;
+        assert_eq!(genome.decide(&features), Action::Straight);
+        features[0] = -1.0;
+        assert_eq!(genome.decide(&features), Action::Left);
+
+        // Only the bias of the last output is left when the feature is 0.
+        let mut genome = follower();
+        *genome.weights.last_mut().unwrap() = 0.5;
+        features[0] = 0.0;
+        assert_eq!(genome.decide(&features), Action::Right);
+    }
+
+    #[test]
+    fn children_take_every_weight_from_a_parent() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mother = Genome::random(4, &mut rng);
+        let father = Genome::random(4, &mut rng);
+        let child = mother.crossover(&father, &mut rng);
+        assert_eq!(child.hidden, 4);
+        assert_eq!(child.weights.len(), mother.weights.len());
+        let inherited = |parent: &Genome| {
+            child
+                .weights
+                .iter()
+                .zip(&parent.weights)
+                .filter(|(a, b)| a.to_bits() == b.to_bits())
+                .count()
+        };
+        let (from_mother, from_father) = (inherited(&mother), inherited(&father));
+        assert_eq!(from_mother + from_father, child.weights.len());
+        assert!(from_mother > 0 && from_father > 0);
+    }
+
+    #[test]
+    fn mutations_happen_as_often_as_the_rate_says() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let genome = Genome::random(4, &mut rng);
+
+        let mut unchanged = genome.clone();
+        unchanged.mutate(0.0, 0.5, &mut rng);
+        assert_eq!(unchanged, genome);
+
+        let mut mutated = genome.clone();
+        mutated.mutate(1.0, 0.5, &mut rng);
+        for (after, before) in mutated.weights.iter().zip(&genome.weights) {
+            assert!(after.to_bits() != before.to_bits() && (after - before).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn genomes_load_as_they_were_saved() {
+        let path = std::env::temp_dir().join(format!("snake-genome-{}.genome", std::process::id()));
+        let genome = Genome::random(3, &mut StdRng::seed_from_u64(3));
+        genome.save(&path).unwrap();
+        let loaded = Genome::load(&path);
+
+        let mut short = genome.clone();
+        short.weights.pop();
+        short.save(&path).unwrap();
+        let error = Genome::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), genome);
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
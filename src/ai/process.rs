@@ -0,0 +1,372 @@
+//! Bots running as their own program, written in any language.
+//!
+//! Before every tick the game writes the board as one line of JSON to the standard input of the
+//! program, and waits for one line with the move on its standard output:
+//!
+//! ```text
+//! > {"tick":12,"you":0,"over":false,"width":18,"height":18,"head":[10,9],"direction":"right",
+//!    "body":[[10,9],[9,9],[8,9]],"snakes":[{"player":0,"alive":true,"score":0,"head":[10,9],
+//!    "direction":"right","body":[[10,9],[9,9],[8,9]]}],"apples":[{"x":4,"y":7,"kind":"apple"}],
+//!    "walls":[[1,1],[1,2],...]}
+//! < {"move":"up"}
+//! ```
+//!
+//! The tiles inside the walls go from 2 to `width + 1` and 2 to `height + 1`, with `y` going up.
+//! `body` starts with the head. A program that does not answer within the timeout keeps going
+//! straight for that tick, and an answer arriving later is thrown away. Once the match is over
+//! the game sends the final board with `"over":true` and closes the input of the program. The
+//! program is stopped once the bot is not needed anymore.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use super::{direction_name, BotError, Brain, MoveMessage, Position};
+use crate::{headless::MatchState, FruitType, SnakeDirection};
+
+pub struct ProcessBot {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// Lines written by the program, read on their own thread so waiting for them can time out.
+    replies: Receiver<String>,
+    /// Answers to boards the program took too long for, thrown away once they arrive.
+    late_replies: usize,
+    timeout: Duration,
+}
+
+impl ProcessBot {
+    /// Starts `command`, split into the program and its arguments on whitespace outside quotes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the command is empty, has an unclosed quote or the program cannot be started.
+    pub fn spawn(command: &str, timeout: Duration) -> io::Result<Self> {
+        let words = split_command(command)?;
+        let (program, args) = words
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the command is empty"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("the output of the program is not piped"))?;
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            stdin: child.stdin.take(),
+            child,
+            replies,
+            late_replies: 0,
+            timeout,
+        })
+    }
+
+    fn send(&mut self, state: &MatchState, player: usize) -> Result<(), BotError> {
+        let stdin = self
+            .stdin
+            .as_mut()
+            .ok_or_else(|| BotError::Crashed("the input of the program is closed".to_owned()))?;
+        let line = serde_json::to_string(&BoardMessage::new(state, player))
+            .map_err(|error| BotError::Crashed(error.to_string()))?;
+        writeln!(stdin, "{line}")
+            .and_then(|()| stdin.flush())
+            .map_err(|error| self.crashed(&error.to_string()))
+    }
+
+    /// Explains why the program stopped talking, with its exit status if it exited.
+    fn crashed(&mut self, reason: &str) -> BotError {
+        match self.child.try_wait() {
+            Ok(Some(status)) => BotError::Crashed(format!("the program exited with {status}")),
+            _ => BotError::Crashed(reason.to_owned()),
+        }
+    }
+}
+
+impl Brain for ProcessBot {
+    fn next_move(
+        &mut self,
+        state: &MatchState,
+        player: usize,
+    ) -> Result<Option<SnakeDirection>, BotError> {
+        self.send(state, player)?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(timeout) {
+                Ok(_) if self.late_replies > 0 => self.late_replies -= 1,
+                Ok(line) => {
+                    return serde_json::from_str::<MoveMessage>(&line)
+                        .ok()
+                        .and_then(|reply| reply.direction())
+                        .map(Some)
+                        .ok_or(BotError::InvalidReply(line));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    self.late_replies += 1;
+                    return Err(BotError::Timeout);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(self.crashed("the output of the program closed"));
+                }
+            }
+        }
+    }
+
+    fn end(&mut self, state: &MatchState, player: usize) {
+        let _ = self.send(state, player);
+        self.stdin = None;
+    }
+}
+
+impl Drop for ProcessBot {
+    /// Stops the program, the bot is only dropped once its match is over or left.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Splits `command` on whitespace into words, keeping the whitespace inside quotes. Single quotes
+/// keep everything up to the next one, double quotes too but for `\"` and `\\`, which stand for
+/// `"` and `\`. Backslashes outside quotes are kept as they are, for paths on Windows.
+fn split_command(command: &str) -> io::Result<Vec<String>> {
+    let unclosed = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the command has an unclosed quote",
+        )
+    };
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            words.extend(word.take());
+            continue;
+        }
+        let word = word.get_or_insert_with(String::new);
+        match c {
+            '\'' => loop {
+                match chars.next().ok_or_else(unclosed)? {
+                    '\'' => break,
+                    c => word.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next().ok_or_else(unclosed)? {
+                    '"' => break,
+                    '\\' => match chars.next().ok_or_else(unclosed)? {
+                        c @ ('"' | '\\') => word.push(c),
+                        c => word.extend(['\\', c]),
+                    },
+                    c => word.push(c),
+                }
+            },
+            c => word.push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[derive(Serialize)]
+struct BoardMessage {
+    tick: u64,
+    you: usize,
+    over: bool,
+    width: i32,
+    height: i32,
+    head: Option<Position>,
+    direction: Option<&'static str>,
+    body: Vec<Position>,
+    snakes: Vec<SnakeMessage>,
+    apples: Vec<AppleMessage>,
+    walls: Vec<Position>,
+}
+
+impl BoardMessage {
+    fn new(state: &MatchState, player: usize) -> Self {
+        let you = state.snakes.iter().find(|snake| snake.player == player);
+        let (width, height) = (state.board.width, state.board.height);
+        let walls = (1..width + 3)
+            .flat_map(|x| [(x, 1), (x, height + 2)])
+            .chain((2..height + 2).flat_map(|y| [(1, y), (width + 2, y)]))
+            .collect();
+        Self {
+            tick: state.tick,
+            you: player,
+            over: state.over,
+            width,
+            height,
+            head: you.map(|snake| snake.body[0]),
+            direction: you.map(|snake| direction_name(snake.direction)),
+            body: you.map(|snake| snake.body.clone()).unwrap_or_default(),
+            snakes: state
+                .snakes
+                .iter()
+                .map(|snake| SnakeMessage {
+                    player: snake.player,
+                    alive: snake.alive,
+                    score: snake.score,
+                    head: snake.body[0],
+                    direction: direction_name(snake.direction),
+                    body: snake.body.clone(),
+                })
+                .collect(),
+            apples: state
+                .fruits
+                .iter()
+                .map(|(fruit, (x, y))| AppleMessage {
+                    x: *x,
+                    y: *y,
+                    kind: fruit_name(*fruit),
+                })
+                .chain(state.bonus_fruits.iter().map(|(x, y)| AppleMessage {
+                    x: *x,
+                    y: *y,
+                    kind: "bonus",
+                }))
+                .collect(),
+            walls,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SnakeMessage {
+    player: usize,
+    alive: bool,
+    score: u32,
+    head: Position,
+    direction: &'static str,
+    body: Vec<Position>,
+}
+
+#[derive(Serialize)]
+struct AppleMessage {
+    x: i32,
+    y: i32,
+    kind: &'static str,
+}
+
+const fn fruit_name(fruit: FruitType) -> &'static str {
+    match fruit {
+        FruitType::Apple => "apple",
+        FruitType::GoldenApple => "golden_apple",
+        FruitType::Berry => "berry",
+        FruitType::Poison => "poison",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    fn board(tick: u64) -> MatchState {
+        MatchState {
+            tick,
+            over: false,
+            filled: false,
+            board: crate::BoardSize::default(),
+            snakes: Vec::new(),
+            fruits: Vec::new(),
+            bonus_fruits: Vec::new(),
+            power_ups: Vec::new(),
+        }
+    }
+
+    /// A bot running `script` in a shell.
+    #[cfg(unix)]
+    fn shell(script: &str, timeout: Duration) -> ProcessBot {
+        let script = script.replace('\\', "\\\\").replace('"', "\\\"");
+        ProcessBot::spawn(&format!("sh -c \"{script}\""), timeout).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn programs_answer_the_board_they_are_sent() {
+        let mut bot = shell(
+            r#"while read -r line; do
+                case "$line" in
+                    *'"tick":0,'*) echo '{"move":"left"}' ;;
+                    *) echo '{"move":"up"}' ;;
+                esac
+            done"#,
+            Duration::from_secs(5),
+        );
+        assert_eq!(bot.next_move(&board(0), 0), Ok(Some(SnakeDirection::Left)));
+        assert_eq!(bot.next_move(&board(1), 0), Ok(Some(SnakeDirection::Up)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn late_answers_are_thrown_away() {
+        let mut bot = shell(
+            r#"read -r line; sleep 1; echo '{"move":"up"}'
+            while read -r line; do echo '{"move":"down"}'; done"#,
+            Duration::from_millis(700),
+        );
+        assert_eq!(bot.next_move(&board(0), 0), Err(BotError::Timeout));
+        assert_eq!(bot.next_move(&board(1), 0), Ok(Some(SnakeDirection::Down)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn programs_that_exit_or_talk_nonsense_fail() {
+        let mut bot = shell(
+            "while read -r line; do echo 'left, please'; done",
+            Duration::from_secs(5),
+        );
+        assert_eq!(
+            bot.next_move(&board(0), 0),
+            Err(BotError::InvalidReply("left, please".to_owned()))
+        );
+
+        let mut bot = shell("exit 3", Duration::from_secs(5));
+        assert!(matches!(
+            bot.next_move(&board(0), 0),
+            Err(BotError::Crashed(_))
+        ));
+
+        assert!(ProcessBot::spawn(" ", Duration::from_secs(5)).is_err());
+        assert!(ProcessBot::spawn("./no-such-snake-bot", Duration::from_secs(5)).is_err());
+    }
+
+    #[test]
+    fn commands_keep_quoted_whitespace() {
+        assert_eq!(
+            split_command(r#"  "my bots/snake" --name 'Big  Snake' C:\bots "say \"hi\"" "#)
+                .unwrap(),
+            [
+                "my bots/snake",
+                "--name",
+                "Big  Snake",
+                r"C:\bots",
+                r#"say "hi""#
+            ]
+        );
+        assert_eq!(split_command(" ").unwrap(), Vec::<String>::new());
+        assert_eq!(split_command("''").unwrap(), [""]);
+        assert!(split_command("'snake").is_err());
+    }
+}
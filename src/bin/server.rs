@@ -0,0 +1,759 @@
+//! Dedicated server hosting matches without a window.
+//!
+//! Clients connect over TCP and talk in lines of text, so a server can be tried out with
+//! `nc localhost 7777`. The server keeps a lobby of rooms, each running its own [`Match`], and
+//! sends the whole board to everyone in a room after every tick. Every client gets a thread writing
+//! its messages, so a slow client never holds up the others, and clients falling too far behind
+//! are disconnected.
+//!
+//! Clients can also send the same lines as UDP datagrams to the same port, with `nc -u`. Every
+//! address sending datagrams is a client of its own, answered with datagrams holding whole lines.
+//! Like any datagram, those may be lost or arrive out of order, so a board can be incomplete, and
+//! UDP clients staying quiet for `UDP_IDLE_TIMEOUT` are disconnected. An empty datagram keeps them
+//! connected.
+//!
+//! Commands sent by clients:
+//!
+//! - `LIST` answers with a `ROOM <name> <mode> <difficulty> <taken>/<seats> <status>` line for
+//!   every room, followed by `END`.
+//! - `CREATE <name> [players] [mode] [difficulty]` opens a room and takes its first seat.
+//! - `JOIN <name>` takes the first free seat of a room, answered with `JOINED <name> <player>`.
+//!   Joining the room the client is already in keeps its seat.
+//! - `LEAVE` gives the seat back. A snake left in a running match dies, just like one of a client
+//!   disconnecting.
+//! - `UP`, `DOWN`, `LEFT` and `RIGHT` steer the snake of the client.
+//!
+//! A match starts once every seat of its room is taken. While it runs, every tick is sent as a
+//! `TICK <number>` line, one `SNAKE <player> <alive|dead> <score> <x>,<y> ...` line per snake
+//! starting at its head, one `FRUIT <kind> <x>,<y>`, `BONUS <x>,<y>` or `POWERUP <kind> <x>,<y>`
+//! line for everything else on the board and `END`. When it is over, `OVER <player|none>` names
+//! the winner and a new match starts shortly after.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{Parser, ValueEnum};
+
+use snake_game_bevy::headless::{
+    BoardSize, Difficulty, GameMode, Match, MatchConfig, MatchState, SnakeDirection,
+};
+
+/// How long the results of a match stay up before the next one starts.
+const RESTART_DELAY: Duration = Duration::from_secs(3);
+/// Clients that do not take the board in this time are disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Clients with this many bytes of messages waiting to be written are disconnected.
+const MAX_QUEUED: usize = 1 << 20;
+const UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Datagrams sent to clients hold whole lines up to this size, to fit in a single packet.
+const MAX_DATAGRAM: usize = 1200;
+const MAX_ROOMS: usize = 64;
+
+#[derive(Parser)]
+#[command(version, about = "Dedicated server for online snake matches")]
+struct Args {
+    /// TCP and UDP port to listen on
+    #[arg(long, default_value_t = 7777)]
+    port: u16,
+}
+
+type ClientId = u64;
+
+/// Clients connected over TCP and UDP share the same numbers.
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+enum Event {
+    Connected(ClientId, Outbox),
+    Line(ClientId, String),
+    Disconnected(ClientId),
+}
+
+/// The UDP clients, by the address they send from.
+type Peers = Arc<Mutex<HashMap<SocketAddr, Peer>>>;
+
+struct Peer {
+    client: ClientId,
+    last_seen: Instant,
+}
+
+/// Where the messages to a client are written.
+enum Connection {
+    Tcp(TcpStream),
+    Udp {
+        socket: UdpSocket,
+        address: SocketAddr,
+        peers: Peers,
+    },
+}
+
+impl Connection {
+    fn write(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.write_all(message),
+            Self::Udp {
+                socket, address, ..
+            } => datagrams(message)
+                .into_iter()
+                .try_for_each(|datagram| socket.send_to(datagram, *address).map(drop)),
+        }
+    }
+
+    /// Stops reading from the client, so the next message it sends makes it a new client.
+    fn close(self, client: ClientId) {
+        match self {
+            Self::Tcp(stream) => {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            Self::Udp { address, peers, .. } => {
+                let mut peers = peers.lock().unwrap_or_else(PoisonError::into_inner);
+                if peers
+                    .get(&address)
+                    .is_some_and(|peer| peer.client == client)
+                {
+                    peers.remove(&address);
+                }
+            }
+        }
+    }
+}
+
+/// Messages waiting for the thread writing them to a client, so sending them never blocks.
+struct Outbox {
+    messages: Sender<Vec<u8>>,
+    /// Bytes sent to the thread that it did not write yet.
+    queued: Arc<AtomicUsize>,
+}
+
+impl Outbox {
+    /// Starts the thread writing to `connection`, until writing fails or the client is dropped.
+    fn spawn(client: ClientId, mut connection: Connection) -> Self {
+        let (messages, receiver) = mpsc::channel::<Vec<u8>>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let written = Arc::clone(&queued);
+        thread::spawn(move || {
+            for message in receiver {
+                if let Err(error) = connection.write(&message) {
+                    eprintln!("Dropping client {client}: {error}");
+                    break;
+                }
+                written.fetch_sub(message.len(), Ordering::Relaxed);
+            }
+            connection.close(client);
+        });
+        Self { messages, queued }
+    }
+
+    /// Queues `message`, `false` if the client is gone or too far behind.
+    fn push(&self, message: &str) -> bool {
+        if self.queued.load(Ordering::Relaxed) + message.len() > MAX_QUEUED {
+            return false;
+        }
+        self.queued.fetch_add(message.len(), Ordering::Relaxed);
+        self.messages.send(message.as_bytes().to_vec()).is_ok()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RoomStatus {
+    /// Waiting for every seat to be taken.
+    Waiting,
+    Playing {
+        next_tick: Instant,
+    },
+    Over {
+        restart_at: Instant,
+    },
+}
+
+struct Room {
+    config: MatchConfig,
+    game: Match,
+    seats: Vec<Option<ClientId>>,
+    status: RoomStatus,
+}
+
+impl Room {
+    fn new(config: MatchConfig) -> Self {
+        Self {
+            game: Match::new(config.clone()),
+            seats: vec![None; usize::from(config.players)],
+            config,
+            status: RoomStatus::Waiting,
+        }
+    }
+
+    fn taken(&self) -> usize {
+        self.seats.iter().flatten().count()
+    }
+
+    fn player_of(&self, client: ClientId) -> Option<usize> {
+        self.seats.iter().position(|seat| *seat == Some(client))
+    }
+
+    /// Gives the seat of `player` back, nobody steers its snake anymore so it dies if the match
+    /// runs.
+    fn empty_seat(&mut self, player: usize) {
+        self.seats[player] = None;
+        if matches!(self.status, RoomStatus::Playing { .. }) {
+            self.game.forfeit(player);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    clients: HashMap<ClientId, Outbox>,
+    rooms: BTreeMap<String, Room>,
+}
+
+impl Server {
+    fn send(&mut self, client: ClientId, message: &str) {
+        let Some(outbox) = self.clients.get(&client) else {
+            return;
+        };
+        if !outbox.push(message) {
+            eprintln!("Dropping client {client}: it is not keeping up");
+            self.disconnect(client);
+        }
+    }
+
+    fn broadcast(&mut self, room: &str, message: &str) {
+        let clients: Vec<ClientId> = self
+            .rooms
+            .get(room)
+            .map(|room| room.seats.iter().flatten().copied().collect())
+            .unwrap_or_default();
+        for client in clients {
+            self.send(client, message);
+        }
+    }
+
+    fn room_of(&self, client: ClientId) -> Option<(String, usize)> {
+        self.rooms
+            .iter()
+            .find_map(|(name, room)| Some((name.clone(), room.player_of(client)?)))
+    }
+
+    fn handle(&mut self, client: ClientId, line: &str) {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return;
+        };
+        let result = match command.to_ascii_uppercase().as_str() {
+            "LIST" => {
+                self.list(client);
+                Ok(())
+            }
+            "CREATE" => self.create(client, &words.collect::<Vec<_>>()),
+            "JOIN" => words
+                .next()
+                .ok_or_else(|| "JOIN needs a room name".to_owned())
+                .and_then(|name| self.join(client, name)),
+            "LEAVE" => {
+                self.leave(client);
+                Ok(())
+            }
+            "UP" => self.steer(client, SnakeDirection::Up),
+            "DOWN" => self.steer(client, SnakeDirection::Down),
+            "LEFT" => self.steer(client, SnakeDirection::Left),
+            "RIGHT" => self.steer(client, SnakeDirection::Right),
+            _ => Err(format!("Unknown command {command}")),
+        };
+        if let Err(error) = result {
+            self.send(client, &format!("ERROR {error}\n"));
+        }
+    }
+
+    fn list(&mut self, client: ClientId) {
+        let mut message = String::new();
+        for (name, room) in &self.rooms {
+            let status = match room.status {
+                RoomStatus::Waiting => "waiting",
+                RoomStatus::Playing { .. } | RoomStatus::Over { .. } => "playing",
+            };
+            let _ = writeln!(
+                message,
+                "ROOM {name} {} {} {}/{} {status}",
+                value_name(&room.config.mode),
+                value_name(&room.config.difficulty),
+                room.taken(),
+                room.seats.len(),
+            );
+        }
+        message.push_str("END\n");
+        self.send(client, &message);
+    }
+
+    fn create(&mut self, client: ClientId, words: &[&str]) -> Result<(), String> {
+        let [name, options @ ..] = words else {
+            return Err("CREATE needs a room name".to_owned());
+        };
+        if self.rooms.contains_key(*name) {
+            return Err(format!("Room {name} already exists"));
+        }
+        if self.rooms.len() >= MAX_ROOMS {
+            return Err("Too many rooms".to_owned());
+        }
+        let players = match options.first() {
+            Some(players) => players
+                .parse()
+                .ok()
+                .filter(|players| (1..=4).contains(players))
+                .ok_or_else(|| format!("Invalid player count {players}"))?,
+            None => 2,
+        };
+        let mode = match options.get(1) {
+            Some(mode) => GameMode::from_str(mode, true)?,
+            None => GameMode::Classic,
+        };
+        let difficulty = match options.get(2) {
+            Some(difficulty) => Difficulty::from_str(difficulty, true)?,
+            None => Difficulty::Normal,
+        };
+        self.rooms.insert(
+            (*name).to_owned(),
+            Room::new(MatchConfig {
+                mode,
+                difficulty,
+                players,
+                bots: Vec::new(),
+                bot_timeout: Duration::ZERO,
+                board: BoardSize::default(),
+                apples: None,
+                seed: rand::random(),
+            }),
+        );
+        self.join(client, name)
+    }
+
+    fn join(&mut self, client: ClientId, name: &str) -> Result<(), String> {
+        if let Some((current, player)) = self.room_of(client) {
+            if current == name {
+                self.send(client, &format!("JOINED {name} {player}\n"));
+                return Ok(());
+            }
+        }
+        self.leave(client);
+        let room = self
+            .rooms
+            .get_mut(name)
+            .ok_or_else(|| format!("No room named {name}"))?;
+        let player = room
+            .seats
+            .iter()
+            .position(Option::is_none)
+            .ok_or_else(|| format!("Room {name} is full"))?;
+        room.seats[player] = Some(client);
+        let full = room.taken() == room.seats.len();
+        if full && room.status == RoomStatus::Waiting {
+            room.status = RoomStatus::Playing {
+                next_tick: Instant::now() + room.game.tick_interval(),
+            };
+        }
+        self.send(client, &format!("JOINED {name} {player}\n"));
+        if full {
+            self.broadcast(name, &format!("START {name}\n"));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, client: ClientId) {
+        let Some((name, player)) = self.room_of(client) else {
+            return;
+        };
+        let Some(room) = self.rooms.get_mut(&name) else {
+            return;
+        };
+        room.empty_seat(player);
+        if room.taken() == 0 {
+            self.rooms.remove(&name);
+        }
+        self.send(client, &format!("LEFT {name}\n"));
+    }
+
+    fn steer(&mut self, client: ClientId, direction: SnakeDirection) -> Result<(), String> {
+        let (name, player) = self
+            .room_of(client)
+            .ok_or_else(|| "Not in a room".to_owned())?;
+        if let Some(room) = self.rooms.get_mut(&name) {
+            room.game.steer(player, direction);
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, client: ClientId) {
+        if let Some((name, player)) = self.room_of(client) {
+            if let Some(room) = self.rooms.get_mut(&name) {
+                room.empty_seat(player);
+                if room.taken() == 0 {
+                    self.rooms.remove(&name);
+                }
+            }
+        }
+        self.clients.remove(&client);
+    }
+
+    /// Simulates the rooms whose next tick is due, returning when the next one will be.
+    fn update(&mut self, now: Instant) -> Option<Instant> {
+        let names: Vec<String> = self.rooms.keys().cloned().collect();
+        for name in names {
+            let Some(room) = self.rooms.get_mut(&name) else {
+                continue;
+            };
+            match room.status {
+                RoomStatus::Playing { next_tick } if next_tick <= now => {
+                    let playing = room.game.step();
+                    let state = room.game.state();
+                    room.status = if playing {
+                        RoomStatus::Playing {
+                            next_tick: next_tick + room.game.tick_interval(),
+                        }
+                    } else {
+                        RoomStatus::Over {
+                            restart_at: now + RESTART_DELAY,
+                        }
+                    };
+                    self.broadcast(&name, &board_message(&state));
+                    if !playing {
+                        let winner = state
+                            .winner()
+                            .map_or_else(|| "none".to_owned(), |player| player.to_string());
+                        self.broadcast(&name, &format!("OVER {winner}\n"));
+                    }
+                }
+                RoomStatus::Over { restart_at } if restart_at <= now => {
+                    room.game.restart();
+                    room.status = if room.taken() == room.seats.len() {
+                        RoomStatus::Playing {
+                            next_tick: now + room.game.tick_interval(),
+                        }
+                    } else {
+                        RoomStatus::Waiting
+                    };
+                    if room.status != RoomStatus::Waiting {
+                        self.broadcast(&name, &format!("START {name}\n"));
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.rooms
+            .values()
+            .filter_map(|room| match room.status {
+                RoomStatus::Waiting => None,
+                RoomStatus::Playing { next_tick } => Some(next_tick),
+                RoomStatus::Over { restart_at } => Some(restart_at),
+            })
+            .min()
+    }
+}
+
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_owned())
+        .unwrap_or_default()
+}
+
+fn board_message(state: &MatchState) -> String {
+    let mut message = format!("TICK {}\n", state.tick);
+    for snake in &state.snakes {
+        let _ = write!(
+            message,
+            "SNAKE {} {} {}",
+            snake.player,
+            if snake.alive { "alive" } else { "dead" },
+            snake.score
+        );
+        for (x, y) in &snake.body {
+            let _ = write!(message, " {x},{y}");
+        }
+        message.push('\n');
+    }
+    for (fruit, (x, y)) in &state.fruits {
+        let _ = writeln!(message, "FRUIT {fruit:?} {x},{y}");
+    }
+    for (x, y) in &state.bonus_fruits {
+        let _ = writeln!(message, "BONUS {x},{y}");
+    }
+    for (kind, (x, y)) in &state.power_ups {
+        let _ = writeln!(message, "POWERUP {kind:?} {x},{y}");
+    }
+    message.push_str("END\n");
+    message
+}
+
+/// Packs the lines of a message into datagrams, a line longer than `MAX_DATAGRAM` gets one of its
+/// own.
+fn datagrams(message: &[u8]) -> Vec<&[u8]> {
+    let mut datagrams = Vec::new();
+    let (mut start, mut end) = (0, 0);
+    for line in message.split_inclusive(|byte| *byte == b'\n') {
+        if end > start && end - start + line.len() > MAX_DATAGRAM {
+            datagrams.push(&message[start..end]);
+            start = end;
+        }
+        end += line.len();
+    }
+    if end > start {
+        datagrams.push(&message[start..end]);
+    }
+    datagrams
+}
+
+/// Accepts clients forever, every one of them gets a thread reading its lines.
+fn accept_clients(listener: TcpListener, events: Sender<Event>) {
+    for stream in listener.incoming() {
+        let id = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Could not accept a client: {error}");
+                continue;
+            }
+        };
+        let reader = match stream.try_clone() {
+            Ok(reader) => reader,
+            Err(error) => {
+                eprintln!("Could not accept a client: {error}");
+                continue;
+            }
+        };
+        if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+            || events
+                .send(Event::Connected(
+                    id,
+                    Outbox::spawn(id, Connection::Tcp(stream)),
+                ))
+                .is_err()
+        {
+            continue;
+        }
+        let events = events.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if events.send(Event::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(Event::Disconnected(id));
+        });
+    }
+}
+
+/// Reads datagrams forever, every address sending them is a client until it stays quiet for
+/// `UDP_IDLE_TIMEOUT`.
+fn receive_datagrams(socket: UdpSocket, events: Sender<Event>) {
+    let peers = Peers::default();
+    let mut buffer = vec![0; 65_536];
+    if let Err(error) = socket.set_read_timeout(Some(UDP_IDLE_TIMEOUT / 4)) {
+        eprintln!("Not accepting UDP clients: {error}");
+        return;
+    }
+    loop {
+        let received = socket.recv_from(&mut buffer);
+        let mut known = peers.lock().unwrap_or_else(PoisonError::into_inner);
+        match received {
+            Ok((size, address)) => {
+                let client = if let Some(peer) = known.get_mut(&address) {
+                    peer.last_seen = Instant::now();
+                    peer.client
+                } else {
+                    let Ok(writer) = socket.try_clone() else {
+                        continue;
+                    };
+                    let client = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+                    let connection = Connection::Udp {
+                        socket: writer,
+                        address,
+                        peers: Arc::clone(&peers),
+                    };
+                    known.insert(
+                        address,
+                        Peer {
+                            client,
+                            last_seen: Instant::now(),
+                        },
+                    );
+                    if events
+                        .send(Event::Connected(client, Outbox::spawn(client, connection)))
+                        .is_err()
+                    {
+                        return;
+                    }
+                    client
+                };
+                for line in String::from_utf8_lossy(&buffer[..size]).lines() {
+                    if events.send(Event::Line(client, line.to_owned())).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(error) => eprintln!("Could not read a datagram: {error}"),
+        }
+        known.retain(|_, peer| {
+            let idle = peer.last_seen.elapsed() > UDP_IDLE_TIMEOUT;
+            if idle {
+                let _ = events.send(Event::Disconnected(peer.client));
+            }
+            !idle
+        });
+    }
+}
+
+fn serve(events: &Receiver<Event>) {
+    let mut server = Server::default();
+    let mut next_update = None;
+    loop {
+        let event = next_update.map_or_else(
+            || events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            |next_update: Instant| {
+                events.recv_timeout(next_update.saturating_duration_since(Instant::now()))
+            },
+        );
+        match event {
+            Ok(Event::Connected(client, outbox)) => {
+                server.clients.insert(client, outbox);
+                server.send(
+                    client,
+                    &format!("WELCOME snake-game-server {}\n", env!("CARGO_PKG_VERSION")),
+                );
+            }
+            Ok(Event::Line(client, line)) => server.handle(client, &line),
+            Ok(Event::Disconnected(client)) => server.disconnect(client),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        next_update = server.update(Instant::now());
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let listener = TcpListener::bind(("0.0.0.0", args.port))?;
+    let socket = UdpSocket::bind(("0.0.0.0", args.port))?;
+    println!("Listening on port {}", args.port);
+    let (sender, receiver) = mpsc::channel();
+    let datagram_events = sender.clone();
+    thread::spawn(move || accept_clients(listener, sender));
+    thread::spawn(move || receive_datagrams(socket, datagram_events));
+    serve(&receiver);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joining_the_same_room_keeps_the_seat() {
+        let mut server = Server::default();
+        server.handle(1, "CREATE den 2");
+        server.handle(1, "JOIN den");
+        assert_eq!(server.rooms["den"].seats, [Some(1), None]);
+    }
+
+    #[test]
+    fn snakes_left_behind_die() {
+        let mut server = Server::default();
+        server.handle(1, "CREATE den 3");
+        server.handle(2, "JOIN den");
+        server.handle(2, "LEAVE");
+        server.handle(2, "JOIN den");
+        server.handle(3, "JOIN den");
+        let alive = |server: &mut Server| -> Vec<bool> {
+            let room = server.rooms.get_mut("den").unwrap();
+            room.game
+                .state()
+                .snakes
+                .iter()
+                .map(|snake| snake.alive)
+                .collect()
+        };
+        // Seats given back before the match starts are taken again by living snakes.
+        assert_eq!(alive(&mut server), [true, true, true]);
+
+        server.handle(2, "LEAVE");
+        assert_eq!(alive(&mut server), [true, false, true]);
+        server.update(Instant::now() + Duration::from_secs(1));
+        assert!(matches!(
+            server.rooms["den"].status,
+            RoomStatus::Playing { .. }
+        ));
+
+        server.disconnect(3);
+        server.update(Instant::now() + Duration::from_secs(2));
+        assert_eq!(alive(&mut server), [true, false, false]);
+        assert!(matches!(
+            server.rooms["den"].status,
+            RoomStatus::Over { .. }
+        ));
+    }
+
+    #[test]
+    fn datagrams_hold_whole_lines() {
+        let line = "SNAKE 0 alive 0 1,1 1,2 1,3\n".repeat(100);
+        let long = format!("{}\n", "x".repeat(MAX_DATAGRAM * 2));
+        let message = format!("{line}{long}END\n");
+        let datagrams = datagrams(message.as_bytes());
+        assert_eq!(datagrams.concat(), message.as_bytes());
+        assert!(datagrams.iter().all(|datagram| datagram.ends_with(b"\n")));
+        assert!(datagrams
+            .iter()
+            .all(|datagram| datagram.len() <= MAX_DATAGRAM || datagram.len() == long.len()));
+    }
+
+    #[test]
+    fn a_client_not_reading_is_dropped_without_waiting_for_it() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _idle = listener.accept().unwrap();
+        let outbox = Outbox::spawn(1, Connection::Tcp(stream));
+        let board = "x".repeat(1 << 16);
+        let start = Instant::now();
+        assert!((0..MAX_QUEUED * 4 / board.len()).any(|_| !outbox.push(&board)));
+        assert!(start.elapsed() < WRITE_TIMEOUT);
+    }
+
+    #[test]
+    fn clients_can_play_over_udp() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || receive_datagrams(socket, sender));
+        thread::spawn(move || serve(&receiver));
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client.send_to(b"CREATE den 1\n", address).unwrap();
+        let mut lines = Vec::new();
+        let mut buffer = vec![0; 65_536];
+        while !lines.iter().any(|line: &String| line.starts_with("TICK")) {
+            let (size, _) = client.recv_from(&mut buffer).unwrap();
+            lines.extend(
+                String::from_utf8_lossy(&buffer[..size])
+                    .lines()
+                    .map(str::to_owned),
+            );
+        }
+        assert!(lines[0].starts_with("WELCOME"));
+        assert_eq!(lines[1..3], ["JOINED den 0", "START den"]);
+    }
+}
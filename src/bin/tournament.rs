@@ -0,0 +1,538 @@
+//! Runs many matches between bots without a window and sums up how every bot did.
+//!
+//! Every bot given with `--bot` plays in every match. A strategy name is a built-in bot, an
+//! `http://` address a Battlesnake server and anything else a command talking JSON lines over its
+//! standard input and output. Match `n` is played with the seed `--seed + n`, and the bots take
+//! turns starting on every row of the board, so a run can be repeated and no bot keeps the best
+//! spot.
+//!
+//! A snake wins when it is the only one left alive at the end of a match, which for a single bot
+//! means filling the board. The summary has one row per bot with its wins, draws and losses, the
+//! average length of its snake and why its snakes died, as CSV or JSON.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+use serde::Serialize;
+
+use snake_game_bevy::headless::{
+    BoardSize, BotKind, Difficulty, GameMode, Match, MatchConfig, MatchState, SnakeState,
+};
+
+const MAX_BOTS: usize = 4;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Runs matches between bots and sums up how every bot did"
+)]
+struct Args {
+    /// Bot playing in every match: a strategy, the http:// address of a Battlesnake server or a command
+    #[arg(long = "bot", value_name = "BOT", required = true)]
+    bots: Vec<BotKind>,
+    /// Number of matches to play
+    #[arg(long, default_value_t = 1000)]
+    games: u64,
+    /// Seed of the first match, every next match adds one to it
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Number of matches played at the same time, one for every core if not given
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+    #[arg(long, value_enum, default_value_t = GameMode::Classic)]
+    mode: GameMode,
+    #[arg(long, value_enum, default_value_t = Difficulty::Normal)]
+    difficulty: Difficulty,
+    /// Number of tiles inside the walls, like 24x16
+    #[arg(long, value_name = "SIZE", default_value_t = BoardSize::default())]
+    board: BoardSize,
+    /// Number of fruits on the board at the same time, overrides the mode default
+    #[arg(long)]
+    apples: Option<usize>,
+    /// How long a bot program or server gets to answer every move
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = 100)]
+    bot_timeout: u64,
+    /// Matches still going after this many ticks end in a draw
+    #[arg(long, default_value_t = 50_000)]
+    max_ticks: u64,
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// File to write the summary to, instead of the standard output
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// How the snake of one bot did in one match.
+struct Seat {
+    /// Index of the bot in `--bot`.
+    bot: usize,
+    outcome: Outcome,
+    length: usize,
+    score: u32,
+    timeouts: u32,
+    /// Why the snake died, or why its bot stopped steering it.
+    crash: Option<String>,
+}
+
+struct MatchResult {
+    ticks: u64,
+    seats: Vec<Seat>,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    games: u64,
+    average_ticks: f64,
+    bots: Vec<BotSummary>,
+}
+
+#[derive(Serialize, Default)]
+struct BotSummary {
+    bot: String,
+    games: u64,
+    wins: u64,
+    draws: u64,
+    losses: u64,
+    win_rate: f64,
+    average_length: f64,
+    average_score: f64,
+    timeouts: u64,
+    /// How many snakes of the bot crashed for every reason.
+    crashes: BTreeMap<String, u64>,
+}
+
+fn play(args: &Args, number: u64) -> MatchResult {
+    // Every match moves the bots one row further down the board.
+    let count = args.bots.len();
+    let first = usize::try_from(number % count as u64).unwrap_or_default();
+    let order: Vec<usize> = (0..count).map(|seat| (first + seat) % count).collect();
+    let mut game = Match::new(MatchConfig {
+        mode: args.mode,
+        difficulty: args.difficulty,
+        players: 0,
+        bots: order.iter().map(|bot| args.bots[*bot].clone()).collect(),
+        bot_timeout: Duration::from_millis(args.bot_timeout),
+        board: args.board,
+        apples: args.apples,
+        seed: args.seed.wrapping_add(number),
+    });
+    let mut causes = vec![None; count];
+    loop {
+        let playing = game.step();
+        let state = game.state();
+        record_deaths(&state, &mut causes);
+        if !playing || state.tick >= args.max_ticks {
+            break;
+        }
+    }
+    let state = game.state();
+    let winner = state.winner();
+    let seats = state
+        .snakes
+        .iter()
+        .map(|snake| {
+            let report = game.bot_report(snake.player).unwrap_or_default();
+            let outcome = if winner == Some(snake.player) {
+                Outcome::Win
+            } else if snake.alive {
+                Outcome::Draw
+            } else {
+                Outcome::Loss
+            };
+            Seat {
+                bot: order[snake.player],
+                outcome,
+                length: snake.body.len(),
+                score: snake.score,
+                timeouts: report.timeouts,
+                crash: report
+                    .crash
+                    .map(|reason| format!("bot crashed: {reason}"))
+                    .or_else(|| causes[snake.player].map(str::to_owned)),
+            }
+        })
+        .collect();
+    MatchResult {
+        ticks: state.tick,
+        seats,
+    }
+}
+
+/// Finds out what killed the snakes that died on the latest tick, while the board is still the way
+/// they ran into it. Snakes that died earlier are not on the board anymore.
+fn record_deaths(state: &MatchState, causes: &mut [Option<&'static str>]) {
+    let died = |snake: &SnakeState| !snake.alive && causes[snake.player].is_none();
+    if !state.snakes.iter().any(died) {
+        return;
+    }
+    let mut board = state.clone();
+    board
+        .snakes
+        .retain(|snake| snake.alive || causes[snake.player].is_none());
+    for snake in board.snakes.iter().filter(|snake| !snake.alive) {
+        causes[snake.player] = Some(death_cause(&board, snake));
+    }
+}
+
+/// Guesses what killed `snake` from where its head ended up.
+fn death_cause(state: &MatchState, snake: &SnakeState) -> &'static str {
+    let head = snake.body[0];
+    let others = state
+        .snakes
+        .iter()
+        .filter(|other| other.player != snake.player);
+    if !state.board.contains(head) {
+        "wall"
+    } else if snake.body.len() == 1 {
+        "poison"
+    } else if others.clone().any(|other| other.body[0] == head) {
+        "head-on"
+    } else if snake.body[1..].contains(&head) {
+        "own tail"
+    } else if others.into_iter().any(|other| other.body.contains(&head)) {
+        "other snake"
+    } else {
+        "unknown"
+    }
+}
+
+/// Plays every match, spread over `threads` threads. A match that panics counts as a draw that
+/// crashed every bot in it, so the rest of the run still adds up.
+fn play_all(args: &Args, threads: usize) -> io::Result<Vec<MatchResult>> {
+    let next_game = AtomicU64::new(0);
+    let workers: Vec<Vec<(u64, MatchResult)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let game = next_game.fetch_add(1, Ordering::Relaxed);
+                        if game >= args.games {
+                            return results;
+                        }
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| play(args, game)))
+                            .unwrap_or_else(|panic| panicked(args, panic.as_ref()));
+                        results.push((game, result));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .map_err(|_| io::Error::other("a worker panicked, the summary would be wrong"))
+            })
+            .collect::<io::Result<_>>()
+    })?;
+    let mut results: Vec<(u64, MatchResult)> = workers.into_iter().flatten().collect();
+    results.sort_by_key(|(game, _)| *game);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// The result of a match that panicked, with the panic message as the crash of every bot.
+fn panicked(args: &Args, panic: &(dyn Any + Send)) -> MatchResult {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message");
+    MatchResult {
+        ticks: 0,
+        seats: (0..args.bots.len())
+            .map(|bot| Seat {
+                bot,
+                outcome: Outcome::Draw,
+                length: 0,
+                score: 0,
+                timeouts: 0,
+                crash: Some(format!("match panicked: {message}")),
+            })
+            .collect(),
+    }
+}
+
+fn summarize(args: &Args, results: &[MatchResult]) -> Summary {
+    let mut bots: Vec<BotSummary> = args
+        .bots
+        .iter()
+        .map(|bot| BotSummary {
+            bot: bot.to_string(),
+            ..Default::default()
+        })
+        .collect();
+    let mut lengths = vec![0; bots.len()];
+    let mut scores = vec![0; bots.len()];
+    for seat in results.iter().flat_map(|result| &result.seats) {
+        let bot = &mut bots[seat.bot];
+        bot.games += 1;
+        match seat.outcome {
+            Outcome::Win => bot.wins += 1,
+            Outcome::Draw => bot.draws += 1,
+            Outcome::Loss => bot.losses += 1,
+        }
+        bot.timeouts += u64::from(seat.timeouts);
+        if let Some(crash) = &seat.crash {
+            *bot.crashes.entry(crash.clone()).or_default() += 1;
+        }
+        lengths[seat.bot] += seat.length as u64;
+        scores[seat.bot] += u64::from(seat.score);
+    }
+    for (bot, (length, score)) in bots.iter_mut().zip(lengths.into_iter().zip(scores)) {
+        let games = bot.games.max(1) as f64;
+        bot.win_rate = bot.wins as f64 / games;
+        bot.average_length = length as f64 / games;
+        bot.average_score = score as f64 / games;
+    }
+    let ticks: u64 = results.iter().map(|result| result.ticks).sum();
+    Summary {
+        games: results.len() as u64,
+        average_ticks: ticks as f64 / results.len().max(1) as f64,
+        bots,
+    }
+}
+
+fn write_csv(summary: &Summary, output: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        output,
+        "bot,games,wins,draws,losses,win_rate,average_length,average_score,average_ticks,timeouts,crashes"
+    )?;
+    for bot in &summary.bots {
+        let crashes: Vec<String> = bot
+            .crashes
+            .iter()
+            .map(|(reason, count)| format!("{reason}: {count}"))
+            .collect();
+        writeln!(
+            output,
+            "{},{},{},{},{},{:.4},{:.2},{:.2},{:.2},{},{}",
+            csv_field(&bot.bot),
+            bot.games,
+            bot.wins,
+            bot.draws,
+            bot.losses,
+            bot.win_rate,
+            bot.average_length,
+            bot.average_score,
+            summary.average_ticks,
+            bot.timeouts,
+            csv_field(&crashes.join("; ")),
+        )?;
+    }
+    Ok(())
+}
+
+/// Quotes `field` if it has anything that would break the columns apart.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    if args.bots.len() > MAX_BOTS {
+        Args::command()
+            .error(
+                ErrorKind::TooManyValues,
+                format!("at most {MAX_BOTS} bots fit on the board"),
+            )
+            .exit();
+    }
+    // A mistyped strategy name is taken for a command, better to find out before the first match.
+    for bot in &args.bots {
+        if let Err(error) = bot.try_start(Duration::from_millis(args.bot_timeout)) {
+            Args::command()
+                .error(ErrorKind::InvalidValue, format!("bot `{bot}`: {error}"))
+                .exit();
+        }
+    }
+    let threads = args
+        .threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let start = Instant::now();
+    let results = play_all(&args, threads)?;
+    eprintln!(
+        "Played {} matches in {:.1?}, {threads} at a time",
+        results.len(),
+        start.elapsed()
+    );
+    let summary = summarize(&args, &results);
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        Format::Csv => write_csv(&summary, &mut output)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut output, &summary)?;
+            writeln!(output)?;
+        }
+    }
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use snake_game_bevy::headless::SnakeDirection;
+
+    use super::*;
+
+    fn args(bots: &[&str]) -> Args {
+        let flags = bots.iter().flat_map(|bot| ["--bot", bot]);
+        Args::parse_from(std::iter::once("snake-game-tournament").chain(flags))
+    }
+
+    fn seat(bot: usize, outcome: Outcome, length: usize, crash: Option<&str>) -> Seat {
+        Seat {
+            bot,
+            outcome,
+            length,
+            score: 0,
+            timeouts: 0,
+            crash: crash.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn the_summary_tallies_every_seat_of_its_bot() {
+        let results = [
+            MatchResult {
+                ticks: 10,
+                seats: vec![
+                    seat(0, Outcome::Win, 6, None),
+                    seat(1, Outcome::Loss, 4, Some("wall")),
+                ],
+            },
+            MatchResult {
+                ticks: 30,
+                seats: vec![
+                    seat(1, Outcome::Draw, 8, None),
+                    seat(0, Outcome::Draw, 10, None),
+                ],
+            },
+            MatchResult {
+                ticks: 20,
+                seats: vec![
+                    seat(0, Outcome::Loss, 2, Some("wall")),
+                    seat(1, Outcome::Loss, 3, Some("head-on")),
+                ],
+            },
+        ];
+        let summary = summarize(&args(&["greedy", "cautious"]), &results);
+        assert_eq!(summary.games, 3);
+        assert!((summary.average_ticks - 20.0).abs() < f64::EPSILON);
+        let tallies: Vec<_> = summary
+            .bots
+            .iter()
+            .map(|bot| (bot.bot.as_str(), bot.games, bot.wins, bot.draws, bot.losses))
+            .collect();
+        assert_eq!(tallies, [("greedy", 3, 1, 1, 1), ("cautious", 3, 0, 1, 2)]);
+        let greedy = &summary.bots[0];
+        assert!((greedy.win_rate - 1.0 / 3.0).abs() < f64::EPSILON);
+        assert!((greedy.average_length - 6.0).abs() < f64::EPSILON);
+        let crashes = &summary.bots[1].crashes;
+        assert_eq!(crashes.get("wall"), Some(&1));
+        assert_eq!(crashes.get("head-on"), Some(&1));
+    }
+
+    #[test]
+    fn csv_quotes_names_with_commas_and_quotes() {
+        assert_eq!(csv_field("greedy"), "greedy");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+
+        let summary = summarize(&args(&["./bot --name \"Big, Snake\""]), &[]);
+        let mut output = Vec::new();
+        write_csv(&summary, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let row = output.lines().nth(1).unwrap();
+        assert!(
+            row.starts_with("\"./bot --name \"\"Big, Snake\"\"\",0,0,0,0,"),
+            "{row}"
+        );
+    }
+
+    #[test]
+    fn panicked_matches_crash_every_bot_in_them() {
+        let args = args(&["greedy", "cautious"]);
+        let panic = panic::catch_unwind(|| panic!("no free tile")).unwrap_err();
+        let result = panicked(&args, panic.as_ref());
+        let summary = summarize(&args, &[result]);
+        for bot in &summary.bots {
+            assert_eq!((bot.games, bot.draws), (1, 1));
+            assert_eq!(bot.crashes.get("match panicked: no free tile"), Some(&1));
+        }
+    }
+
+    fn snake(player: usize, body: &[(i32, i32)]) -> SnakeState {
+        SnakeState {
+            player,
+            alive: player != 0,
+            score: 0,
+            direction: SnakeDirection::Up,
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn deaths_are_put_down_to_what_the_head_ran_into() {
+        let other = [(6, 5), (7, 5), (8, 5)];
+        for (body, others, cause) in [
+            (vec![(1, 5), (2, 5), (3, 5)], &[][..], "wall"),
+            (vec![(5, 5)], &[], "poison"),
+            (vec![(6, 5), (5, 5), (4, 5)], &other[..], "head-on"),
+            (
+                vec![(5, 5), (5, 6), (6, 6), (6, 5), (5, 5)],
+                &[],
+                "own tail",
+            ),
+            (vec![(7, 5), (7, 4), (7, 3)], &other, "other snake"),
+            (vec![(5, 5), (5, 4), (5, 3)], &other, "unknown"),
+        ] {
+            let mut snakes = vec![snake(0, &body)];
+            if !others.is_empty() {
+                snakes.push(snake(1, others));
+            }
+            let state = MatchState {
+                tick: 0,
+                over: true,
+                filled: false,
+                board: BoardSize::default(),
+                snakes,
+                fruits: Vec::new(),
+                bonus_fruits: Vec::new(),
+                power_ups: Vec::new(),
+            };
+            assert_eq!(death_cause(&state, &state.snakes[0]), cause);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Evolves neural networks that play snake, without a window.
+//!
+//! Every generation, each genome of the population plays the same seeded matches through an
+//! [`Environment`], and its fitness is the reward it earned. The best genomes carry over
+//! unchanged, and the rest of the next generation are children of two parents picked by
+//! tournament, with their weights crossed over and mutated. The best genome of a generation is
+//! saved when it beats the one saved before on the same matches, and plays in the windowed game
+//! with `--bot-genome`.
+
+use std::{
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use clap::Parser;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use snake_game_bevy::{
+    environment::{Encoding, Environment, EnvironmentConfig, Rewards},
+    headless::{BoardSize, BotKind, Difficulty, GameMode, Genome},
+};
+
+/// Genomes picked at random to find each parent, the fittest of them wins.
+const TOURNAMENT_SIZE: usize = 3;
+
+#[derive(Parser)]
+#[command(version, about = "Evolves neural networks that play snake")]
+struct Args {
+    /// Number of genomes in every generation
+    #[arg(long, default_value_t = 100)]
+    population: usize,
+    #[arg(long, default_value_t = 50)]
+    generations: u32,
+    /// Matches every genome plays per generation
+    #[arg(long, default_value_t = 5)]
+    games: u64,
+    /// Best genomes kept unchanged in the next generation
+    #[arg(long, default_value_t = 5)]
+    elite: usize,
+    /// Number of neurons in the hidden layer
+    #[arg(long, default_value_t = 12)]
+    hidden: usize,
+    /// Chance of every weight of a child changing, from 0 to 1
+    #[arg(long, default_value_t = 0.1, value_parser = parse_probability)]
+    mutation_rate: f64,
+    /// Largest change of a mutated weight, above 0
+    #[arg(long, default_value_t = 0.5, value_parser = parse_strength)]
+    mutation_strength: f32,
+    /// Seed for the first population and the matches played
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Number of genomes playing at the same time, one for every core if not given
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+    #[arg(long, value_enum, default_value_t = GameMode::Classic)]
+    mode: GameMode,
+    /// Number of tiles inside the walls, like 24x16
+    #[arg(long, value_name = "SIZE", default_value_t = BoardSize::default())]
+    board: BoardSize,
+    /// Bot playing against every genome, a strategy, the http:// address of a Battlesnake server or a command
+    #[arg(long = "opponent", value_name = "BOT")]
+    opponents: Vec<BotKind>,
+    /// Matches are cut short after this many ticks without eating
+    #[arg(long, default_value_t = 500)]
+    starve_ticks: u64,
+    /// Start from the genome saved in this file instead of a random population
+    #[arg(long, value_name = "FILE")]
+    resume: Option<PathBuf>,
+    /// File the best genome is saved to
+    #[arg(long, value_name = "FILE", default_value = "best.genome")]
+    output: PathBuf,
+}
+
+fn parse_probability(value: &str) -> Result<f64, String> {
+    let rate: f64 = value.parse().map_err(|error| format!("{error}"))?;
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("{rate} is not between 0 and 1"))
+    }
+}
+
+fn parse_strength(value: &str) -> Result<f32, String> {
+    let strength: f32 = value.parse().map_err(|error| format!("{error}"))?;
+    if strength.is_finite() && strength > 0.0 {
+        Ok(strength)
+    } else {
+        Err(format!("{strength} is not a number above 0"))
+    }
+}
+
+/// Plays every match of a generation with `genome` and adds up the rewards.
+fn fitness(args: &Args, genome: &Genome, generation: u32) -> f32 {
+    let mut environment = Environment::new(EnvironmentConfig {
+        mode: args.mode,
+        difficulty: Difficulty::Normal,
+        board: args.board,
+        opponents: args.opponents.clone(),
+        bot_timeout: Duration::from_millis(100),
+        encoding: Encoding::Features,
+        rewards: Rewards {
+            // A small nudge towards fruits, so the first random genomes are not all worth nothing.
+            approach: 0.01,
+            ..Rewards::default()
+        },
+        ..EnvironmentConfig::default()
+    });
+    let mut total = 0.0;
+    for game in 0..args.games {
+        let mut observation = environment.reset(
+            args.seed
+                .wrapping_add(u64::from(generation) * args.games)
+                .wrapping_add(game),
+        );
+        let (mut score, mut hungry) = (0, 0);
+        loop {
+            let step = environment.step(genome.decide(&observation.data));
+            total += step.reward;
+            if step.info.score > score {
+                (score, hungry) = (step.info.score, 0);
+            } else {
+                hungry += 1;
+            }
+            if step.done || hungry > args.starve_ticks {
+                break;
+            }
+            observation = step.observation;
+        }
+    }
+    total / args.games as f32
+}
+
+/// Plays every genome, spread over `threads` threads.
+fn evaluate(args: &Args, population: &[Genome], generation: u32, threads: usize) -> Vec<f32> {
+    let next = AtomicUsize::new(0);
+    let mut scores: Vec<(usize, f32)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut scores = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(genome) = population.get(index) else {
+                            return scores;
+                        };
+                        scores.push((index, fitness(args, genome, generation)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a genome panicked"))
+            .collect()
+    });
+    scores.sort_by_key(|(index, _)| *index);
+    scores.into_iter().map(|(_, score)| score).collect()
+}
+
+/// The fittest of a few genomes picked at random.
+fn pick_parent<'a>(ranked: &'a [(Genome, f32)], rng: &mut impl Rng) -> &'a Genome {
+    let best = (0..TOURNAMENT_SIZE)
+        .map(|_| rng.gen_range(0..ranked.len()))
+        .min()
+        .unwrap_or_default();
+    &ranked[best].0
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let threads = args
+        .threads
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get);
+    let population_size = args.population.max(2);
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let mut population: Vec<Genome> = match &args.resume {
+        Some(path) => {
+            let genome = Genome::load(path)?;
+            let mut population = vec![genome; population_size];
+            for child in &mut population[1..] {
+                child.mutate(args.mutation_rate, args.mutation_strength, &mut rng);
+            }
+            population
+        }
+        None => (0..population_size)
+            .map(|_| Genome::random(args.hidden, &mut rng))
+            .collect(),
+    };
+    let mut best: Option<Genome> = None;
+
+    for generation in 0..args.generations {
+        let scores = evaluate(&args, &population, generation, threads);
+        let mut ranked: Vec<(Genome, f32)> = population.into_iter().zip(scores).collect();
+        ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let average = ranked.iter().map(|(_, score)| score).sum::<f32>() / ranked.len() as f32;
+        eprintln!(
+            "Generation {}: best {:.2}, average {average:.2}",
+            generation + 1,
+            ranked[0].1
+        );
+        // Every generation plays different matches, so the saved genome plays this generation's
+        // matches again before the two are compared.
+        if !best
+            .as_ref()
+            .is_some_and(|best| fitness(&args, best, generation) >= ranked[0].1)
+        {
+            ranked[0].0.save(&args.output)?;
+            best = Some(ranked[0].0.clone());
+        }
+
+        population = ranked
+            .iter()
+            .take(args.elite)
+            .map(|(genome, _)| genome.clone())
+            .collect();
+        while population.len() < population_size {
+            let mut child =
+                pick_parent(&ranked, &mut rng).crossover(pick_parent(&ranked, &mut rng), &mut rng);
+            child.mutate(args.mutation_rate, args.mutation_strength, &mut rng);
+            population.push(child);
+        }
+    }
+    if best.is_some() {
+        eprintln!("Saved the best genome to {}", args.output.display());
+    }
+    Ok(())
+}
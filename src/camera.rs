@@ -0,0 +1,405 @@
+//! The cameras showing the board.
+//!
+//! The board is zoomed in as far as whole pixels allow, or as far as set with + and -. Boards larger
+//! than the window scroll with the head of the first snake, and can also be shown whole on a
+//! minimap in a corner.
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig, prelude::*, render::camera::Viewport,
+    transform::TransformSystem, window::PrimaryWindow,
+};
+
+use bevy_pixel_camera::{PixelCameraPlugin, PixelZoom};
+
+use crate::{BoardSize, Dead, GameState, Player, Snake, SPRITE_SIZE};
+
+/// Shows the board in the window, with the camera settings from the command line.
+pub struct CameraPlugin(pub CameraSettings);
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.0.clone())
+            .init_resource::<Zoom>()
+            .add_plugins(PixelCameraPlugin)
+            .add_systems(Startup, setup_camera)
+            .add_systems(OnEnter(GameState::Playing), center_camera)
+            .add_systems(Update, (zoom_camera, fit_minimap.after(zoom_camera)))
+            .add_systems(
+                PostUpdate,
+                follow_head.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// The zoom picked with + and -, `None` to fit the board in the window. `0` goes back to fitting.
+#[derive(Resource, Default)]
+struct Zoom(Option<i32>);
+
+/// The largest zoom set with +.
+const MAX_ZOOM: i32 = 16;
+
+/// How the camera follows the snakes on boards larger than the screen.
+#[derive(clap::Args, Resource, Clone)]
+pub struct CameraSettings {
+    /// Tiles the head can move away from the middle of the screen before the camera follows it
+    #[arg(long = "camera-dead-zone", value_name = "TILES", default_value_t = 3.0)]
+    dead_zone: f32,
+    /// How quickly the camera catches up with the head, 0 keeps it right on the head
+    #[arg(long = "camera-smoothing", default_value_t = 8.0)]
+    smoothing: f32,
+    /// Show the whole board in a corner when it does not fit on the screen
+    #[arg(long)]
+    minimap: bool,
+}
+
+/// Marks the camera showing the whole board in a corner of the window.
+#[derive(Component)]
+struct Minimap;
+
+/// Part of the window the minimap may cover at most, across and up.
+const MINIMAP_SHARE: f32 = 0.25;
+
+/// Logical pixels between the minimap and the corner of the window.
+const MINIMAP_MARGIN: f32 = 8.0;
+
+fn setup_camera(mut commands: Commands, camera_settings: Res<CameraSettings>) {
+    commands.spawn((Camera2dBundle::default(), PixelZoom::Fixed(2)));
+    if camera_settings.minimap {
+        commands.spawn((
+            Camera2dBundle {
+                camera: Camera {
+                    order: 1,
+                    is_active: false,
+                    ..default()
+                },
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::Custom(Color::BLACK),
+                },
+                ..default()
+            },
+            UiCameraConfig { show_ui: false },
+            Minimap,
+        ));
+    }
+}
+
+/// Zooms the camera in on the board as far as whole pixels allow, or as set with + and -, and
+/// shows it in the middle of the window with the clear color around it.
+fn zoom_camera(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut zoom: ResMut<Zoom>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    board_size: Res<BoardSize>,
+    mut camera_query: Query<(&mut Camera, &mut PixelZoom)>,
+) {
+    // A minimized window has no size to fit the board in.
+    let Some(window) = window_query
+        .get_single()
+        .ok()
+        .filter(|window| window.width() > 0.0 && window.height() > 0.0)
+    else {
+        return;
+    };
+    let fit = fit_zoom(*board_size, Vec2::new(window.width(), window.height()));
+    let current = zoom.0.unwrap_or(fit);
+    if keyboard_input.any_just_pressed([KeyCode::Plus, KeyCode::Equals, KeyCode::NumpadAdd]) {
+        zoom.0 = Some((current + 1).min(MAX_ZOOM));
+    } else if keyboard_input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        zoom.0 = Some((current - 1).max(1));
+    } else if keyboard_input.any_just_pressed([KeyCode::Key0, KeyCode::Numpad0]) {
+        zoom.0 = None;
+    }
+    let level = zoom.0.unwrap_or(fit);
+
+    let viewport = board_viewport(
+        *board_size,
+        level,
+        UVec2::new(window.physical_width(), window.physical_height()),
+        window.physical_width() as f32 / window.width(),
+    );
+    for (mut camera, mut pixel_zoom) in &mut camera_query {
+        if *pixel_zoom != PixelZoom::Fixed(level) {
+            *pixel_zoom = PixelZoom::Fixed(level);
+        }
+        if camera
+            .viewport
+            .as_ref()
+            .map(|old| (old.physical_position, old.physical_size))
+            != Some((viewport.physical_position, viewport.physical_size))
+        {
+            camera.viewport = Some(viewport.clone());
+        }
+    }
+}
+
+/// The largest whole-pixel zoom, from 2 up, that shows the whole table in `window_area`, or 1 if
+/// none does.
+fn fit_zoom(board_size: BoardSize, window_area: Vec2) -> i32 {
+    let (table_width, table_height) = board_size.table();
+    let board = Vec2::new(table_width as f32, table_height as f32) * SPRITE_SIZE;
+    (2..=MAX_ZOOM)
+        .rev()
+        .find(|level| (board * *level as f32).cmple(window_area).all())
+        .unwrap_or(1)
+}
+
+/// The part of a window of `window_size` physical pixels the table takes at zoom `level`, in the
+/// middle of it.
+fn board_viewport(
+    board_size: BoardSize,
+    level: i32,
+    window_size: UVec2,
+    scale_factor: f32,
+) -> Viewport {
+    let (table_width, table_height) = board_size.table();
+    let board = Vec2::new(table_width as f32, table_height as f32) * SPRITE_SIZE;
+    let size = (board * level as f32 * scale_factor)
+        .as_uvec2()
+        .min(window_size)
+        .max(UVec2::ONE);
+    Viewport {
+        physical_position: (window_size.saturating_sub(size)) / 2,
+        physical_size: size,
+        ..default()
+    }
+}
+
+/// The corners of the table in the world, the sprites are centred on their tiles.
+fn table_bounds(board_size: BoardSize) -> Rect {
+    let (table_width, table_height) = board_size.table();
+    Rect::new(
+        -SPRITE_SIZE / 2.0,
+        -SPRITE_SIZE / 2.0,
+        (table_width as f32 - 0.5) * SPRITE_SIZE,
+        (table_height as f32 - 0.5) * SPRITE_SIZE,
+    )
+}
+
+/// Points the cameras at the middle of the board, which can change size between matches.
+fn center_camera(
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+    board_size: Res<BoardSize>,
+) {
+    let center = table_bounds(*board_size).center();
+    for mut transform in &mut camera_query {
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+    }
+}
+
+/// Moves the camera after the head of the first snake still alive once it leaves the dead zone,
+/// without showing anything past the edges of a board larger than the screen.
+fn follow_head(
+    time: Res<Time>,
+    camera_settings: Res<CameraSettings>,
+    board_size: Res<BoardSize>,
+    snake_query: Query<(&Snake, &Player), Without<Dead>>,
+    mut camera_query: Query<(&Camera, &PixelZoom, &mut Transform), Without<Minimap>>,
+) {
+    let bounds = table_bounds(*board_size);
+    let head = snake_query
+        .iter()
+        .min_by_key(|(_, player)| player.0)
+        .map(|(snake, _)| Vec2::new(snake.x as f32, snake.y as f32) * SPRITE_SIZE);
+    for (camera, pixel_zoom, mut transform) in &mut camera_query {
+        let (PixelZoom::Fixed(level), Some(viewport)) =
+            (pixel_zoom, camera.logical_viewport_size())
+        else {
+            continue;
+        };
+        let half_view = viewport / *level as f32 / 2.0;
+        let mut position = transform.translation.truncate();
+        if let Some(head) = head {
+            let target = follow_target(position, head, camera_settings.dead_zone, half_view);
+            position = if camera_settings.smoothing > 0.0 {
+                position.lerp(
+                    target,
+                    1.0 - (-camera_settings.smoothing * time.delta_seconds()).exp(),
+                )
+            } else {
+                target
+            };
+        }
+        position = clamp_to_board(position, half_view, bounds);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// Where the camera at `position` goes for the head to be back inside the dead zone of
+/// `dead_zone` tiles, showing `half_view` around its middle. It stays put while the head is
+/// inside.
+fn follow_target(position: Vec2, head: Vec2, dead_zone: f32, half_view: Vec2) -> Vec2 {
+    // The head stays at least a tile away from the edges of the screen.
+    let dead_zone = Vec2::splat(dead_zone.max(0.0) * SPRITE_SIZE)
+        .min((half_view - SPRITE_SIZE).max(Vec2::ZERO));
+    position.clamp(head - dead_zone, head + dead_zone)
+}
+
+/// Keeps a camera showing `half_view` around `position` from showing anything past `bounds`. A
+/// board smaller than the screen stays in the middle of it.
+fn clamp_to_board(position: Vec2, half_view: Vec2, bounds: Rect) -> Vec2 {
+    let (low, high) = (bounds.min + half_view, bounds.max - half_view);
+    let center = bounds.center();
+    Vec2::new(
+        if low.x < high.x {
+            position.x.clamp(low.x, high.x)
+        } else {
+            center.x
+        },
+        if low.y < high.y {
+            position.y.clamp(low.y, high.y)
+        } else {
+            center.y
+        },
+    )
+}
+
+/// Shows the whole board in the bottom right corner of the window when the main camera cannot
+/// see all of it.
+fn fit_minimap(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    board_size: Res<BoardSize>,
+    main_camera_query: Query<(&Camera, &PixelZoom), Without<Minimap>>,
+    mut minimap_query: Query<(&mut Camera, &mut OrthographicProjection), With<Minimap>>,
+) {
+    let Ok((mut camera, mut projection)) = minimap_query.get_single_mut() else {
+        return;
+    };
+    let Some(window) = window_query
+        .get_single()
+        .ok()
+        .filter(|window| window.width() > 0.0 && window.height() > 0.0)
+    else {
+        return;
+    };
+    let bounds = table_bounds(*board_size);
+    let board = bounds.size();
+    let hidden = main_camera_query.iter().any(|(main_camera, pixel_zoom)| {
+        let PixelZoom::Fixed(level) = pixel_zoom else {
+            return false;
+        };
+        main_camera
+            .logical_viewport_size()
+            .is_some_and(|viewport| (board * *level as f32).cmpgt(viewport).any())
+    });
+    if camera.is_active != hidden {
+        camera.is_active = hidden;
+    }
+    if !hidden {
+        return;
+    }
+
+    let scale = minimap_scale(board, Vec2::new(window.width(), window.height()));
+    let scale_factor = window.physical_width() as f32 / window.width();
+    let size = (board / scale * scale_factor).as_uvec2().max(UVec2::ONE);
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    let margin = Vec2::splat(MINIMAP_MARGIN * scale_factor).as_uvec2();
+    let viewport = Viewport {
+        physical_position: window_size.saturating_sub(size + margin),
+        physical_size: size.min(window_size),
+        ..default()
+    };
+    if camera
+        .viewport
+        .as_ref()
+        .map(|old| (old.physical_position, old.physical_size))
+        != Some((viewport.physical_position, viewport.physical_size))
+    {
+        camera.viewport = Some(viewport);
+    }
+    if (projection.scale - scale).abs() > f32::EPSILON {
+        projection.scale = scale;
+    }
+}
+
+/// World units for every pixel of the minimap, so `board` fits in its share of `window_area`.
+fn minimap_scale(board: Vec2, window_area: Vec2) -> f32 {
+    // Whole world units for every pixel keep the tiles the same size.
+    (board / (window_area * MINIMAP_SHARE))
+        .max_element()
+        .ceil()
+        .max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_board_fits_the_window_with_whole_pixels() {
+        // The default table is 22 tiles, 352 pixels, wide and high.
+        let board = BoardSize::default();
+        assert_eq!(fit_zoom(board, Vec2::new(1280.0, 720.0)), 2);
+        assert_eq!(fit_zoom(board, Vec2::new(1920.0, 1080.0)), 3);
+        assert_eq!(fit_zoom(board, Vec2::new(800.0, 600.0)), 1);
+        assert_eq!(fit_zoom(board, Vec2::new(100_000.0, 100_000.0)), MAX_ZOOM);
+        let wide = BoardSize {
+            width: 76,
+            height: 6,
+        };
+        assert_eq!(fit_zoom(wide, Vec2::new(1920.0, 1080.0)), 1);
+
+        let viewport = board_viewport(board, 2, UVec2::new(1280, 720), 1.0);
+        assert_eq!(viewport.physical_size, UVec2::new(704, 704));
+        assert_eq!(viewport.physical_position, UVec2::new(288, 8));
+        let viewport = board_viewport(board, 2, UVec2::new(2560, 1440), 2.0);
+        assert_eq!(viewport.physical_size, UVec2::new(1408, 1408));
+        let viewport = board_viewport(board, 4, UVec2::new(1280, 720), 1.0);
+        assert_eq!(viewport.physical_size, UVec2::new(1280, 720));
+        assert_eq!(viewport.physical_position, UVec2::ZERO);
+    }
+
+    #[test]
+    fn the_camera_follows_the_head_out_of_the_dead_zone() {
+        let half_view = Vec2::new(160.0, 90.0);
+        let position = Vec2::new(400.0, 400.0);
+        for head in [position, Vec2::new(440.0, 370.0), Vec2::new(352.0, 448.0)] {
+            assert_eq!(follow_target(position, head, 3.0, half_view), position);
+        }
+        assert_eq!(
+            follow_target(position, Vec2::new(460.0, 300.0), 3.0, half_view),
+            Vec2::new(412.0, 348.0)
+        );
+        // The dead zone never lets the head closer than a tile to the edge of the screen.
+        assert_eq!(
+            follow_target(position, Vec2::new(400.0, 500.0), 10.0, half_view),
+            Vec2::new(400.0, 426.0)
+        );
+        assert_eq!(
+            follow_target(position, Vec2::new(410.0, 390.0), 0.0, half_view),
+            Vec2::new(410.0, 390.0)
+        );
+    }
+
+    #[test]
+    fn the_camera_stops_at_the_edges_of_the_board() {
+        let bounds = table_bounds(BoardSize {
+            width: 76,
+            height: 36,
+        });
+        assert_eq!(bounds, Rect::new(-8.0, -8.0, 1272.0, 632.0));
+        let half_view = Vec2::new(160.0, 90.0);
+        assert_eq!(
+            clamp_to_board(Vec2::new(500.0, 300.0), half_view, bounds),
+            Vec2::new(500.0, 300.0)
+        );
+        assert_eq!(
+            clamp_to_board(Vec2::new(0.0, 700.0), half_view, bounds),
+            Vec2::new(152.0, 542.0)
+        );
+        assert_eq!(
+            clamp_to_board(Vec2::new(1300.0, -50.0), half_view, bounds),
+            Vec2::new(1112.0, 82.0)
+        );
+        // A board narrower than the screen stays in the middle of it.
+        assert_eq!(
+            clamp_to_board(Vec2::new(0.0, 0.0), Vec2::new(700.0, 90.0), bounds),
+            Vec2::new(632.0, 82.0)
+        );
+
+        let board = bounds.size();
+        assert!((minimap_scale(board, Vec2::new(1280.0, 720.0)) - 4.0).abs() < f32::EPSILON);
+        assert!((minimap_scale(board, Vec2::new(8000.0, 8000.0)) - 1.0).abs() < f32::EPSILON);
+    }
+}
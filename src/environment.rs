@@ -0,0 +1,455 @@
+//! A reinforcement learning environment over the game rules, shaped like the Gym API.
+//!
+//! An [`Environment`] plays one snake against any bots in a headless [`Match`]. Every
+//! [`Environment::step`] turns the snake or keeps it going straight, plays one tick and returns
+//! what the agent sees afterwards, the reward it earned and whether the episode is over. The
+//! observation is either a grid with one channel per kind of thing on the board, or a short vector
+//! of features around the head, see [`Encoding`]. How much every event is worth is set with
+//! [`Rewards`].
+
+use std::time::Duration;
+
+use crate::{
+    ai::{step, BotKind, DIRECTIONS},
+    headless::{Match, MatchConfig, MatchState, SnakeState},
+    BoardSize, Difficulty, FruitType, GameMode, SnakeDirection,
+};
+
+/// The player the agent steers, the bots get the next ones.
+const AGENT: usize = 0;
+
+/// The settings every episode is played with.
+#[derive(Clone, Debug)]
+pub struct EnvironmentConfig {
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+    pub board: BoardSize,
+    /// Number of fruits on the board at the same time, `None` for the default of the mode.
+    pub apples: Option<usize>,
+    /// Bots playing against the agent, at most 3.
+    pub opponents: Vec<BotKind>,
+    pub bot_timeout: Duration,
+    pub encoding: Encoding,
+    pub rewards: Rewards,
+    /// Episodes still going after this many ticks are cut short.
+    pub max_ticks: u64,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Classic,
+            difficulty: Difficulty::Normal,
+            board: BoardSize::default(),
+            apples: None,
+            opponents: Vec::new(),
+            bot_timeout: Duration::from_millis(100),
+            encoding: Encoding::Grid,
+            rewards: Rewards::default(),
+            max_ticks: 10_000,
+        }
+    }
+}
+
+/// How the board is turned into numbers for the agent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    /// `GRID_CHANNELS` layers of `height` rows of `width` tiles, `1.0` where the layer has
+    /// something. The layers are the head of the agent, the rest of its body, the other snakes,
+    /// the fruits worth eating and poison. Row 0 is the bottom of the board.
+    Grid,
+    /// `FEATURE_COUNT` numbers seen from the head: whether going straight, left or right is
+    /// deadly, the direction the snake heads in, where the closest fruit is, how far away it is
+    /// across and up, and how long the snake is compared to the board.
+    Features,
+}
+
+pub const GRID_CHANNELS: usize = 5;
+pub const FEATURE_COUNT: usize = 14;
+
+/// What every event is worth to the agent.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rewards {
+    /// For every point scored, so better fruits are worth more.
+    pub score: f32,
+    pub death: f32,
+    /// For being the last snake alive, or filling the board alone.
+    pub win: f32,
+    /// For every tick survived, negative to hurry the agent up.
+    pub tick: f32,
+    /// For every tile the head gets closer to the closest fruit, and taken away for every tile it
+    /// gets further.
+    pub approach: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            death: -1.0,
+            win: 1.0,
+            tick: 0.0,
+            approach: 0.0,
+        }
+    }
+}
+
+/// Where the agent steers its snake, seen from the head.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Straight,
+    Left,
+    Right,
+}
+
+impl Action {
+    pub const ALL: [Self; 3] = [Self::Straight, Self::Left, Self::Right];
+
+    /// The direction a snake heading in `direction` goes in.
+    #[must_use]
+    pub const fn direction(self, direction: SnakeDirection) -> SnakeDirection {
+        match self {
+            Self::Straight => direction,
+            Self::Left => turn_left(direction),
+            Self::Right => turn_right(direction),
+        }
+    }
+}
+
+impl TryFrom<usize> for Action {
+    type Error = usize;
+
+    fn try_from(index: usize) -> Result<Self, Self::Error> {
+        Self::ALL.get(index).copied().ok_or(index)
+    }
+}
+
+/// What the agent sees, laid out flat as described by `shape`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Observation {
+    /// `[GRID_CHANNELS, height, width]` for a grid, `[FEATURE_COUNT]` for features.
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+/// What happened in a step besides the reward.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StepInfo {
+    pub tick: u64,
+    pub score: u32,
+    pub length: usize,
+    /// Whether the agent won the match.
+    pub won: bool,
+    /// Whether the episode was cut short by `max_ticks` instead of ending.
+    pub truncated: bool,
+}
+
+/// The result of one step, like the tuple of a Gym environment.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Step {
+    pub observation: Observation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+pub struct Environment {
+    config: EnvironmentConfig,
+    game: Match,
+    state: MatchState,
+}
+
+impl Environment {
+    /// Starts the first episode with the seed `0`.
+    #[must_use]
+    pub fn new(config: EnvironmentConfig) -> Self {
+        let mut game = Match::new(match_config(&config, 0));
+        let state = game.state();
+        Self {
+            config,
+            game,
+            state,
+        }
+    }
+
+    /// Starts a new episode with the fruits placed from `seed`, returning the first observation.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = Match::new(match_config(&self.config, seed));
+        self.state = self.game.state();
+        self.observe()
+    }
+
+    /// Steers the snake of the agent, plays one tick and tells how it went.
+    ///
+    /// An episode without a snake for the agent is done right away.
+    pub fn step(&mut self, action: Action) -> Step {
+        let Some(before) = self.agent().cloned() else {
+            return Step {
+                observation: self.observe(),
+                reward: 0.0,
+                done: true,
+                info: StepInfo {
+                    tick: self.state.tick,
+                    score: 0,
+                    length: 0,
+                    won: false,
+                    truncated: false,
+                },
+            };
+        };
+        let playing = !self.state.over && before.alive;
+        if playing {
+            self.game.steer(AGENT, action.direction(before.direction));
+            self.game.step();
+            self.state = self.game.state();
+        }
+        // Snakes stay on the board when they die, so the agent still has one.
+        let agent = self.agent().unwrap_or(&before);
+        let alive = self.state.snakes.iter().filter(|snake| snake.alive).count();
+        let won = self.state.over && agent.alive && alive == 1;
+        let truncated = !self.state.over && self.state.tick >= self.config.max_ticks;
+
+        let rewards = self.config.rewards;
+        // Stepping an episode that is already done is worth nothing.
+        let mut reward = if playing { rewards.tick } else { 0.0 };
+        reward += rewards.score * agent.score.saturating_sub(before.score) as f32;
+        if playing && !agent.alive {
+            reward += rewards.death;
+        }
+        if playing && won {
+            reward += rewards.win;
+        }
+        if agent.alive {
+            if let (Some(from), Some(to)) = (
+                closest_fruit(&self.state, before.body[0]),
+                closest_fruit(&self.state, agent.body[0]),
+            ) {
+                // Both distances are to the fruits left now, so eating one is not a step back.
+                reward += rewards.approach
+                    * (distance(before.body[0], from) - distance(agent.body[0], to)) as f32;
+            }
+        }
+
+        Step {
+            observation: self.observe(),
+            reward,
+            done: self.state.over || !agent.alive || truncated,
+            info: StepInfo {
+                tick: self.state.tick,
+                score: agent.score,
+                length: agent.body.len(),
+                won,
+                truncated,
+            },
+        }
+    }
+
+    /// The shape of every observation, which only depends on the encoding and the board.
+    #[must_use]
+    pub fn observation_shape(&self) -> Vec<usize> {
+        match self.config.encoding {
+            Encoding::Grid => vec![
+                GRID_CHANNELS,
+                self.config.board.height.unsigned_abs() as usize,
+                self.config.board.width.unsigned_abs() as usize,
+            ],
+            Encoding::Features => vec![FEATURE_COUNT],
+        }
+    }
+
+    /// Everything on the board, for agents that want more than the observation.
+    #[must_use]
+    pub const fn state(&self) -> &MatchState {
+        &self.state
+    }
+
+    fn agent(&self) -> Option<&SnakeState> {
+        self.state.snakes.iter().find(|snake| snake.player == AGENT)
+    }
+
+    fn observe(&self) -> Observation {
+        let data = match self.config.encoding {
+            Encoding::Grid => self.grid(),
+            Encoding::Features => self.agent().map_or_else(
+                || vec![0.0; FEATURE_COUNT],
+                |agent| features(&self.state, agent),
+            ),
+        };
+        Observation {
+            shape: self.observation_shape(),
+            data,
+        }
+    }
+
+    fn grid(&self) -> Vec<f32> {
+        let board = self.state.board;
+        let (width, height) = (
+            board.width.unsigned_abs() as usize,
+            board.height.unsigned_abs() as usize,
+        );
+        let mut grid = vec![0.0; GRID_CHANNELS * width * height];
+        let mut mark = |channel: usize, (x, y): (i32, i32)| {
+            if board.contains((x, y)) {
+                let (x, y) = (
+                    (x - 2).unsigned_abs() as usize,
+                    (y - 2).unsigned_abs() as usize,
+                );
+                grid[(channel * height + y) * width + x] = 1.0;
+            }
+        };
+        for snake in self.state.snakes.iter().filter(|snake| snake.alive) {
+            if snake.player == AGENT {
+                mark(0, snake.body[0]);
+                snake.body[1..].iter().for_each(|tile| mark(1, *tile));
+            } else {
+                snake.body.iter().for_each(|tile| mark(2, *tile));
+            }
+        }
+        for (fruit, tile) in &self.state.fruits {
+            mark(if *fruit == FruitType::Poison { 4 } else { 3 }, *tile);
+        }
+        for tile in &self.state.bonus_fruits {
+            mark(3, *tile);
+        }
+        grid
+    }
+}
+
+/// The [`Encoding::Features`] of the board seen from the head of `snake`.
+#[must_use]
+pub fn features(state: &MatchState, snake: &SnakeState) -> Vec<f32> {
+    let head = snake.body[0];
+    let board = state.board;
+    let deadly = |direction| {
+        let next = step(head, direction);
+        !board.contains(next)
+            || state
+                .snakes
+                .iter()
+                .filter(|snake| snake.alive)
+                .any(|snake| {
+                    // The tip of a tail moves away before the head gets there.
+                    snake.body[..snake.body.len() - 1].contains(&next)
+                })
+    };
+    let flag = |value: bool| if value { 1.0 } else { 0.0 };
+    let mut features: Vec<f32> = Action::ALL
+        .into_iter()
+        .map(|action| flag(deadly(action.direction(snake.direction))))
+        .collect();
+    features.extend(DIRECTIONS.map(|direction| flag(snake.direction == direction)));
+    let fruit = closest_fruit(state, head).unwrap_or(head);
+    features.extend([
+        flag(fruit.1 > head.1),
+        flag(fruit.1 < head.1),
+        flag(fruit.0 < head.0),
+        flag(fruit.0 > head.0),
+        (fruit.0 - head.0) as f32 / board.width as f32,
+        (fruit.1 - head.1) as f32 / board.height as f32,
+        snake.body.len() as f32 / board.tile_count() as f32,
+    ]);
+    features
+}
+
+fn match_config(config: &EnvironmentConfig, seed: u64) -> MatchConfig {
+    MatchConfig {
+        mode: config.mode,
+        difficulty: config.difficulty,
+        players: 1,
+        bots: config.opponents.clone(),
+        bot_timeout: config.bot_timeout,
+        board: config.board,
+        apples: config.apples,
+        seed,
+    }
+}
+
+/// The closest fruit worth eating, bonus fruits included.
+fn closest_fruit(state: &MatchState, from: (i32, i32)) -> Option<(i32, i32)> {
+    state
+        .fruits
+        .iter()
+        .filter(|(fruit, _)| *fruit != FruitType::Poison)
+        .map(|(_, tile)| *tile)
+        .chain(state.bonus_fruits.iter().copied())
+        .min_by_key(|tile| distance(from, *tile))
+}
+
+const fn distance(from: (i32, i32), to: (i32, i32)) -> i32 {
+    (from.0 - to.0).abs() + (from.1 - to.1).abs()
+}
+
+const fn turn_left(direction: SnakeDirection) -> SnakeDirection {
+    match direction {
+        SnakeDirection::Up => SnakeDirection::Left,
+        SnakeDirection::Left => SnakeDirection::Down,
+        SnakeDirection::Down => SnakeDirection::Right,
+        SnakeDirection::Right => SnakeDirection::Up,
+    }
+}
+
+const fn turn_right(direction: SnakeDirection) -> SnakeDirection {
+    match direction {
+        SnakeDirection::Up => SnakeDirection::Right,
+        SnakeDirection::Right => SnakeDirection::Down,
+        SnakeDirection::Down => SnakeDirection::Left,
+        SnakeDirection::Left => SnakeDirection::Up,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_starts_the_episode_of_the_seed() {
+        let mut environment = Environment::new(EnvironmentConfig::default());
+        let observation = environment.reset(3);
+        assert_eq!(observation.shape, environment.observation_shape());
+        assert_eq!(
+            observation.data.len(),
+            observation.shape.iter().product::<usize>()
+        );
+        let tiles = observation.data.len() / GRID_CHANNELS;
+        let heads = observation.data[..tiles].iter().filter(|tile| **tile > 0.0);
+        assert_eq!(heads.count(), 1);
+
+        environment.step(Action::Left);
+        assert_eq!(environment.reset(3), observation);
+    }
+
+    #[test]
+    fn running_into_a_wall_ends_the_episode() {
+        let mut environment = Environment::new(EnvironmentConfig {
+            encoding: Encoding::Features,
+            rewards: Rewards {
+                score: 0.0,
+                ..Rewards::default()
+            },
+            ..EnvironmentConfig::default()
+        });
+        let last = std::iter::repeat_with(|| environment.step(Action::Straight))
+            .take(100)
+            .find(|step| step.done)
+            .unwrap();
+        assert!((last.reward - Rewards::default().death).abs() < f32::EPSILON);
+        assert!(!last.info.truncated);
+        assert_eq!(last.observation.data.len(), FEATURE_COUNT);
+
+        let after = environment.step(Action::Straight);
+        assert!(after.done);
+        assert!(after.reward.abs() < f32::EPSILON);
+        assert_eq!(after.info.tick, last.info.tick);
+    }
+
+    #[test]
+    fn long_episodes_are_cut_short() {
+        let mut environment = Environment::new(EnvironmentConfig {
+            max_ticks: 2,
+            ..EnvironmentConfig::default()
+        });
+        assert!(!environment.step(Action::Straight).done);
+        let step = environment.step(Action::Straight);
+        assert!(step.done && step.info.truncated);
+    }
+}
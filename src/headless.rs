@@ -0,0 +1,236 @@
+//! Matches simulated without a window.
+//!
+//! A [`Match`] runs the exact same rules as the windowed game in its own `App`, but only advances
+//! when asked to, so it can be played as fast or as slow as the caller wants.
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    ai::Bot,
+    power_ups::PowerUp,
+    rules::{run_game_tick, RulesPlugin, Tick, TickInputs},
+    Apple, BoardFilled, BonusFruit, Dead, GameState, MoveTimer, Player, Score, Snake, Tail,
+    TextureAtlasHandle,
+};
+pub use crate::{
+    ai::{
+        choose_direction, BattlesnakeBot, BotError, BotKind, BotReport, BotStrategy, Brain, Genome,
+        ProcessBot,
+    },
+    power_ups::PowerUpKind,
+    BoardSize, Difficulty, FruitType, GameMode, SnakeDirection,
+};
+
+/// The settings a match is played with.
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    pub mode: GameMode,
+    pub difficulty: Difficulty,
+    /// Number of snakes steered by players.
+    pub players: u8,
+    /// Computer snakes joining the players, at most 4 snakes in total.
+    pub bots: Vec<BotKind>,
+    /// How long bot programs get to answer every move.
+    pub bot_timeout: Duration,
+    pub board: BoardSize,
+    /// Number of fruits on the board at the same time, `None` for the default of the mode.
+    pub apples: Option<usize>,
+    pub seed: u64,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Classic,
+            difficulty: Difficulty::Normal,
+            players: 1,
+            bots: Vec::new(),
+            bot_timeout: Duration::from_millis(100),
+            board: BoardSize::default(),
+            apples: None,
+            seed: 0,
+        }
+    }
+}
+
+/// A match without a window, advanced one tick at a time.
+pub struct Match {
+    app: App,
+}
+
+impl Match {
+    #[must_use]
+    pub fn new(config: MatchConfig) -> Self {
+        Self {
+            app: rules_app(config),
+        }
+    }
+
+    /// Turns the snake of `player` on the next tick.
+    pub fn steer(&mut self, player: usize, direction: SnakeDirection) {
+        let number = self.app.world.resource::<Tick>().number;
+        self.app
+            .world
+            .resource_mut::<TickInputs>()
+            .0
+            .entry(number)
+            .or_default()
+            .push((Player(player), direction));
+    }
+
+    /// Takes the snake of `player` out of the match as if it crashed, for a player leaving it.
+    pub fn forfeit(&mut self, player: usize) {
+        let world = &mut self.app.world;
+        let snakes: Vec<Entity> = world
+            .query_filtered::<(Entity, &Player), With<Snake>>()
+            .iter(world)
+            .filter(|(_, snake_player)| snake_player.0 == player)
+            .map(|(snake, _)| snake)
+            .collect();
+        for snake in snakes {
+            world.entity_mut(snake).insert(Dead);
+        }
+    }
+
+    /// Simulates one tick, returning `false` once the match is over.
+    pub fn step(&mut self) -> bool {
+        if self.is_over() {
+            return false;
+        }
+        run_game_tick(&mut self.app.world);
+        self.app.update();
+        !self.is_over()
+    }
+
+    #[must_use]
+    pub fn is_over(&self) -> bool {
+        *self.app.world.resource::<State<GameState>>().get() == GameState::GameOver
+    }
+
+    /// Starts over with the same settings, the fruits will show up in different places.
+    pub fn restart(&mut self) {
+        self.app
+            .world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+        self.app.update();
+    }
+
+    /// How long the next tick lasts when the match is played in real time.
+    pub fn tick_interval(&mut self) -> Duration {
+        let world = &mut self.app.world;
+        world.query::<&MoveTimer>().single(world).0.duration()
+    }
+
+    pub fn state(&mut self) -> MatchState {
+        MatchState::from_world(&mut self.app.world)
+    }
+
+    /// How the bot steering the snake of `player` did, `None` if a player steers it.
+    pub fn bot_report(&mut self, player: usize) -> Option<BotReport> {
+        let world = &mut self.app.world;
+        world
+            .query::<(&Bot, &Player)>()
+            .iter(world)
+            .find(|(_, bot_player)| bot_player.0 == player)
+            .map(|(bot, _)| bot.report.clone())
+    }
+}
+
+/// The app a [`Match`] plays in, ready for the first tick, for tests needing the whole world.
+pub(crate) fn rules_app(config: MatchConfig) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, RulesPlugin(config)))
+        .insert_resource(TextureAtlasHandle(Handle::default()));
+    app.update();
+    app
+}
+
+/// Everything on the board after the latest tick.
+#[derive(Clone, Debug)]
+pub struct MatchState {
+    /// The number of the next tick.
+    pub tick: u64,
+    pub over: bool,
+    /// Whether the match ended because the snakes covered the whole board.
+    pub filled: bool,
+    pub board: BoardSize,
+    pub snakes: Vec<SnakeState>,
+    pub fruits: Vec<(FruitType, (i32, i32))>,
+    pub bonus_fruits: Vec<(i32, i32)>,
+    pub power_ups: Vec<(PowerUpKind, (i32, i32))>,
+}
+
+impl MatchState {
+    pub(crate) fn from_world(world: &mut World) -> Self {
+        let mut tail_query = world.query::<&Tail>();
+        let mut snakes: Vec<SnakeState> = world
+            .query::<(&Snake, &Player, &Score, Has<Dead>)>()
+            .iter(world)
+            .map(|(snake, player, score, dead)| SnakeState {
+                player: player.0,
+                alive: !dead,
+                score: score.0,
+                direction: snake.direction,
+                body: std::iter::once((snake.x, snake.y))
+                    .chain(
+                        tail_query
+                            .iter_many(world, &snake.tail)
+                            .map(|tail| (tail.x, tail.y)),
+                    )
+                    .collect(),
+            })
+            .collect();
+        snakes.sort_by_key(|snake| snake.player);
+        let fruits = world
+            .query::<(&Apple, &FruitType)>()
+            .iter(world)
+            .map(|(apple, fruit)| (*fruit, (apple.x, apple.y)))
+            .collect();
+        let bonus_fruits = world
+            .query::<&BonusFruit>()
+            .iter(world)
+            .map(|bonus| (bonus.x, bonus.y))
+            .collect();
+        let power_ups = world
+            .query::<&PowerUp>()
+            .iter(world)
+            .map(|power_up| (power_up.kind, (power_up.x, power_up.y)))
+            .collect();
+        Self {
+            tick: world.resource::<Tick>().number,
+            over: *world.resource::<State<GameState>>().get() == GameState::GameOver,
+            filled: world.resource::<BoardFilled>().0,
+            board: *world.resource::<BoardSize>(),
+            snakes,
+            fruits,
+            bonus_fruits,
+            power_ups,
+        }
+    }
+
+    /// The player whose snake outlived all the others, if the match had more than one, or filled
+    /// the board on its own. Several snakes sharing a filled board have no winner.
+    #[must_use]
+    pub fn winner(&self) -> Option<usize> {
+        let mut alive = self.snakes.iter().filter(|snake| snake.alive);
+        match (alive.next(), alive.next()) {
+            (Some(snake), None) if self.over && (self.filled || self.snakes.len() > 1) => {
+                Some(snake.player)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SnakeState {
+    pub player: usize,
+    pub alive: bool,
+    pub score: u32,
+    pub direction: SnakeDirection,
+    /// Every tile of the snake, starting with the head.
+    pub body: Vec<(i32, i32)>,
+}
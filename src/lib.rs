@@ -0,0 +1,1577 @@
+mod ai;
+mod camera;
+pub mod environment;
+pub mod headless;
+mod netplay;
+mod power_ups;
+mod rules;
+mod snapshot;
+mod sound;
+mod sprites;
+
+pub use sound::music::render_wav;
+
+use std::{
+    fmt::{Debug, Display, Formatter},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use bevy::{
+    app::AppExit,
+    asset::io::embedded::EmbeddedAssetRegistry,
+    core::FrameCount,
+    ecs::system::SystemParam,
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+};
+
+use clap::{error::ErrorKind, CommandFactory, Parser, ValueEnum};
+
+use rand::prelude::*;
+
+use ai::{AutopilotPlugin, Bot, BotKind, BotStrategy, RealTime};
+use camera::{CameraPlugin, CameraSettings};
+use headless::MatchConfig;
+use netplay::{LobbyPlugin, Netcode, NetplayPlugin, Session, DISCOVERY_PORT};
+use power_ups::{SlowMotion, SLOW_MOTION_FACTOR};
+use rules::{advance_move_timer, run_game_ticks, RulesPlugin, Tick};
+use sound::{SoundPlugin, SoundSettings};
+use sprites::{Palette, Skin, SkinPlugin, SpriteFrames};
+
+const SPRITE_SIZE: f32 = 16.0;
+const WIN_BANNER_SECONDS: f32 = 3.0;
+const BONUS_FRUIT_LIFETIME: f32 = 6.0;
+const BONUS_FRUIT_BLINK_TIME: f32 = 2.0;
+const BONUS_FRUIT_MAX_SCORE: u32 = 10;
+
+fn fullscreen_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let mut window = window_query.single_mut();
+    if keyboard_input.just_pressed(KeyCode::F) {
+        if window.mode == WindowMode::Fullscreen {
+            window.mode = WindowMode::Windowed;
+        } else {
+            window.mode = WindowMode::Fullscreen;
+        }
+    }
+}
+
+fn exit_on_esc_system(keyboard_input: Res<Input<KeyCode>>, mut exit: EventWriter<AppExit>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        exit.send(AppExit);
+    }
+}
+
+macro_rules! embedded_asset {
+    ($embedded:ident, $path:expr) => {
+        $embedded.insert_asset(
+            PathBuf::new(),
+            Path::new($path),
+            include_bytes!(concat!("../assets/", $path)),
+        );
+    };
+}
+
+struct EmbeddedAssetsPlugin;
+
+impl Plugin for EmbeddedAssetsPlugin {
+    fn build(&self, app: &mut App) {
+        let embedded = app.world.resource_mut::<EmbeddedAssetRegistry>();
+        embedded_asset!(embedded, "sprites.png");
+        embedded_asset!(embedded, "sounds/turn.wav");
+        embedded_asset!(embedded, "sounds/eat.wav");
+        embedded_asset!(embedded, "sounds/power_up.wav");
+        embedded_asset!(embedded, "sounds/death.wav");
+        embedded_asset!(embedded, "sounds/menu.wav");
+    }
+}
+
+#[derive(Parser, Clone)]
+#[command(version, about)]
+struct Args {
+    /// Game mode to play
+    #[arg(long, value_enum, default_value_t = GameMode::Classic)]
+    mode: GameMode,
+    /// Number of fruits on the board at the same time, overrides the mode default
+    #[arg(long)]
+    apples: Option<usize>,
+    /// Number of tiles inside the walls, like 24x16
+    #[arg(long, value_name = "SIZE", default_value_t = BoardSize::default())]
+    board: BoardSize,
+    /// How fast the snake starts and how quickly it speeds up
+    #[arg(long, value_enum, default_value_t = Difficulty::Normal)]
+    difficulty: Difficulty,
+    /// Number of players sharing the keyboard
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4))]
+    players: u8,
+    /// Computer snakes joining the match, one for every strategy given
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "STRATEGIES")]
+    bots: Vec<BotStrategy>,
+    /// Computer snake steered by this program, talking JSON lines over its standard input and output.
+    /// Quote the program and arguments that have spaces in them
+    #[arg(long, value_name = "COMMAND")]
+    bot_command: Vec<String>,
+    /// Computer snake steered by the Battlesnake server at this http:// address
+    #[arg(long, value_name = "URL", value_parser = parse_bot_url)]
+    bot_url: Vec<String>,
+    /// Computer snake steered by the neural network saved in this file by snake-game-train
+    #[arg(long, value_name = "FILE")]
+    bot_genome: Vec<PathBuf>,
+    /// How long a bot program gets to answer every move before its snake keeps going straight, at
+    /// most until the next tick
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = 100)]
+    bot_timeout: u64,
+    /// Start with the autopilot steering the first snake, P hands it over and back at any time. It fills
+    /// the board unless both its width and height are odd, then it plays like the cautious bot
+    #[arg(long)]
+    attract: bool,
+    /// Slide the snakes between tiles instead of jumping from one to the next
+    #[arg(long)]
+    smooth: bool,
+    #[command(flatten)]
+    camera: CameraSettings,
+    #[command(flatten)]
+    sound: SoundSettings,
+    /// Skin installed in the skins directory to draw the game with, the built-in sprites if not given
+    #[arg(long, value_name = "NAME")]
+    skin: Option<String>,
+    /// Seed for the random placement of fruits and power-ups, random if not given
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Host an online match on this UDP port and wait for an opponent
+    #[arg(long, value_name = "PORT", conflicts_with = "connect")]
+    host: Option<u16>,
+    /// Join an online match hosted at this address
+    #[arg(long, value_name = "ADDRESS")]
+    connect: Option<SocketAddr>,
+    /// List the online matches hosted on the local network to pick one to join
+    #[arg(long, conflicts_with_all = ["host", "connect"])]
+    browse: bool,
+    /// Name of the hosted match shown to players on the local network
+    #[arg(long, default_value = "Snake")]
+    name: String,
+    /// Where the hosted match is announced, 127.0.0.1:7776 finds it on the same machine
+    #[arg(long, value_name = "ADDRESS", default_value_t = SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT)))]
+    announce_to: SocketAddr,
+    /// Ticks between pressing a key and the move happening in an online match, chosen by the host
+    #[arg(long, default_value_t = 2)]
+    input_delay: u64,
+    /// How an online match deals with late inputs of the opponent, chosen by the host
+    #[arg(long, value_enum, default_value_t = Netcode::Rollback)]
+    netcode: Netcode,
+    /// Delay every packet sent to the opponent by this many milliseconds, to test online matches locally
+    #[arg(long, value_name = "MILLISECONDS", default_value_t = 0)]
+    simulate_latency: u64,
+}
+
+impl Args {
+    fn match_config(&self) -> MatchConfig {
+        MatchConfig {
+            mode: self.mode,
+            difficulty: self.difficulty,
+            players: self.players,
+            bots: self
+                .bots
+                .iter()
+                .copied()
+                .map(BotKind::BuiltIn)
+                .chain(self.bot_command.iter().cloned().map(BotKind::Process))
+                .chain(self.bot_url.iter().cloned().map(BotKind::Http))
+                .chain(self.bot_genome.iter().cloned().map(BotKind::Genome))
+                .collect(),
+            bot_timeout: Duration::from_millis(self.bot_timeout),
+            board: self.board,
+            apples: self.apples,
+            seed: self.seed.unwrap_or_else(random),
+        }
+    }
+}
+
+fn parse_bot_url(url: &str) -> Result<String, String> {
+    match url.parse()? {
+        BotKind::Http(_) => Ok(url.to_owned()),
+        _ => Err(format!("`{url}` is not an http:// address")),
+    }
+}
+
+/// Runs the windowed game with the settings from the command line.
+pub fn run() {
+    let mut args = Args::parse();
+    let bots =
+        args.bots.len() + args.bot_command.len() + args.bot_url.len() + args.bot_genome.len();
+    if usize::from(args.players) + bots > CONTROL_SCHEMES.len() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                format!(
+                    "at most {} snakes fit on the board, counting players and bots",
+                    CONTROL_SCHEMES.len()
+                ),
+            )
+            .exit();
+    }
+    let mut app = App::new();
+    // Added first, so the log shows how connecting to an online match goes.
+    app.add_plugins(
+        DefaultPlugins
+            .set(ImagePlugin::default_nearest())
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Snake Game".to_owned(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+    );
+    let session = start_online_match(&mut args);
+
+    if args.browse {
+        // Inserted before the state is added, so the game starts in the lobby.
+        app.insert_resource(State::new(GameState::Lobby))
+            .add_plugins(LobbyPlugin(args.clone()))
+            .add_systems(OnExit(GameState::Lobby), setup_hud);
+    }
+    app.insert_resource(RealTime)
+        .add_plugins((
+            CameraPlugin(args.camera.clone()),
+            EmbeddedAssetsPlugin,
+            SkinPlugin(args.skin.clone()),
+            SoundPlugin(args.sound.clone()),
+            RulesPlugin(args.match_config()),
+            NetplayPlugin,
+            GameOverPlugin,
+            AutopilotPlugin {
+                enabled: args.attract,
+            },
+        ))
+        .add_systems(
+            Startup,
+            (
+                setup_resources,
+                setup_hud.run_if(not(in_state(GameState::Lobby))),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                run_game_ticks,
+                (draw_snake_sprites, draw_apple_sprite, blink_bonus_fruit).after(run_game_ticks),
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (fullscreen_system, exit_on_esc_system, update_score_text),
+        )
+        .add_systems(
+            Update,
+            (read_keyboard_direction, advance_move_timer)
+                .before(run_game_ticks)
+                .run_if(in_state(GameState::Playing))
+                .run_if(not(resource_exists::<Session>())),
+        );
+    if args.smooth {
+        app.add_systems(
+            Update,
+            glide_snake_sprites
+                .after(draw_snake_sprites)
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+    if let Some(session) = session {
+        app.insert_resource(session);
+    }
+    app.run();
+}
+
+/// Waits for the opponent when hosting or joining an online match, exiting if that fails.
+fn start_online_match(args: &mut Args) -> Option<Session> {
+    let session = if let Some(port) = args.host {
+        Some(Session::host(port, args))
+    } else {
+        args.connect.map(|address| Session::connect(address, args))
+    };
+    match session.transpose() {
+        Ok(session) => session,
+        Err(error) => {
+            error!("Could not start the online match: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Game modes change which fruits show up and how many of them are on the board.
+#[derive(Resource, ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum GameMode {
+    /// One apple at a time, just like the original game.
+    Classic,
+    /// Several fruits at a time, with golden apples and berries mixed in.
+    Orchard,
+    /// Like orchard, but with poison fruits that are worth a lot and shrink the snake.
+    Venom,
+}
+
+impl GameMode {
+    /// How many fruits are on the board at the same time.
+    const fn apple_count(self) -> usize {
+        match self {
+            Self::Classic => 1,
+            Self::Orchard => 3,
+            Self::Venom => 4,
+        }
+    }
+
+    /// The relative chance of each fruit type being spawned.
+    const fn fruit_weights(self) -> &'static [(FruitType, u32)] {
+        match self {
+            Self::Classic => &[(FruitType::Apple, 1)],
+            Self::Orchard => &[
+                (FruitType::Apple, 6),
+                (FruitType::GoldenApple, 1),
+                (FruitType::Berry, 3),
+            ],
+            Self::Venom => &[
+                (FruitType::Apple, 5),
+                (FruitType::GoldenApple, 1),
+                (FruitType::Berry, 2),
+                (FruitType::Poison, 3),
+            ],
+        }
+    }
+}
+
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    const fn speed_curve(self) -> SpeedCurve {
+        match self {
+            Self::Easy => SpeedCurve {
+                start_interval: 0.4,
+                apple_factor: 0.97,
+                min_interval: 0.15,
+                time_factor: None,
+            },
+            Self::Normal => SpeedCurve {
+                start_interval: 0.3,
+                apple_factor: 0.95,
+                min_interval: 0.08,
+                time_factor: None,
+            },
+            Self::Hard => SpeedCurve {
+                start_interval: 0.25,
+                apple_factor: 0.93,
+                min_interval: 0.06,
+                time_factor: Some(0.995),
+            },
+            Self::Insane => SpeedCurve {
+                start_interval: 0.18,
+                apple_factor: 0.9,
+                min_interval: 0.04,
+                time_factor: Some(0.99),
+            },
+        }
+    }
+}
+
+/// Describes how the time between moves changes over the course of a game.
+#[derive(Clone, Copy, Debug)]
+struct SpeedCurve {
+    /// Seconds between moves at the start of a game.
+    start_interval: f32,
+    /// Multiplier applied to the interval for every speed step, usually one per apple eaten.
+    apple_factor: f32,
+    /// The interval never gets shorter than this, no matter how much the snake eats.
+    min_interval: f32,
+    /// Multiplier applied to the interval every second of play, if the snake should also speed up over time.
+    time_factor: Option<f32>,
+}
+
+impl SpeedCurve {
+    /// Keeps an interval between the minimum and the starting interval.
+    const fn clamp(&self, interval: f32) -> f32 {
+        interval.clamp(self.min_interval, self.start_interval)
+    }
+}
+
+/// The current time between moves of the snake, driven by a `SpeedCurve`.
+#[derive(Resource, Clone)]
+struct Speed {
+    curve: SpeedCurve,
+    interval: f32,
+}
+
+impl Speed {
+    const fn new(curve: SpeedCurve) -> Self {
+        Self {
+            curve,
+            interval: curve.start_interval,
+        }
+    }
+
+    const fn reset(&mut self) {
+        self.interval = self.curve.start_interval;
+    }
+
+    /// Moves the interval along the curve, negative steps slow the snake down.
+    fn step(&mut self, steps: i32) {
+        self.interval = self
+            .curve
+            .clamp(self.interval * self.curve.apple_factor.powi(steps));
+    }
+
+    /// Applies the time-based acceleration of the curve for the given amount of seconds.
+    fn accelerate(&mut self, seconds: f32) {
+        if let Some(time_factor) = self.curve.time_factor {
+            self.interval = self.curve.clamp(self.interval * time_factor.powf(seconds));
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Default, States, Debug, Clone, Copy)]
+enum GameState {
+    /// Picking an online match on the local network to join.
+    Lobby,
+    #[default]
+    Playing,
+    GameOver,
+}
+
+/// A simple queue implementation that uses a fixed-size array and wraps around.
+/// When the queue is full, the oldest value is overwritten.
+/// This is used to store the last few directions the player has pressed.
+///
+/// This is necessary because the player can press two directions in one frame and that would cause the snake to only move one tile instead of two.
+///
+/// Why not use a `VecDeque`?
+///
+/// `VecDeque` does not have peeking, which is necessary to check if the player is trying to turn back on itself.
+#[derive(Clone)]
+struct Queue<T> {
+    head: usize,
+    tail: usize,
+    data: [Option<T>; 10],
+}
+
+impl<T: Copy> Queue<T> {
+    /// Creates a new empty queue.
+    #[inline]
+    const fn new() -> Self {
+        Self {
+            head: 0,
+            tail: 0,
+            data: [None; 10],
+        }
+    }
+
+    /// Pushes a value to the back of the queue.
+    /// If the queue is full, the oldest value is overwritten.
+    #[inline]
+    fn push(&mut self, value: T) {
+        self.data[self.tail] = Some(value);
+        self.tail = (self.tail + 1) % self.data.len();
+    }
+
+    /// Pops a value from the front of the queue.
+    /// If the queue is empty, `None` is returned.
+    #[inline]
+    fn pop(&mut self) -> Option<T> {
+        let value = self.data[self.head];
+        if value.is_none() {
+            value?;
+        }
+        self.data[self.head] = None;
+        self.head = (self.head + 1) % self.data.len();
+        value
+    }
+
+    /// Returns the value at the front of the queue without removing it.
+    /// If the queue is empty, `None` is returned.
+    #[inline]
+    const fn peek(&self) -> Option<T> {
+        self.data[self.head]
+    }
+}
+
+impl<T: Debug> Debug for Queue<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.data.iter()).finish()
+    }
+}
+
+impl<T: Copy> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many tiles the snakes can move on, inside the walls.
+///
+/// The tiles inside the walls go from `2` to `width + 1` and `2` to `height + 1`, the walls
+/// surround them and the glass covers one more tile all around.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BoardSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl BoardSize {
+    pub const MIN: i32 = 8;
+    pub const MAX: i32 = 64;
+
+    #[must_use]
+    pub const fn contains(self, (x, y): (i32, i32)) -> bool {
+        x >= 2 && x < self.width + 2 && y >= 2 && y < self.height + 2
+    }
+
+    /// Every tile inside the walls, column by column.
+    pub fn positions(self) -> impl Iterator<Item = (i32, i32)> {
+        (2..self.width + 2).flat_map(move |x| (2..self.height + 2).map(move |y| (x, y)))
+    }
+
+    #[must_use]
+    pub const fn tile_count(self) -> usize {
+        (self.width * self.height).unsigned_abs() as usize
+    }
+
+    /// Width and height of the glass under the whole board, walls included.
+    const fn table(self) -> (i32, i32) {
+        (self.width + 4, self.height + 4)
+    }
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        Self {
+            width: 18,
+            height: 18,
+        }
+    }
+}
+
+impl Display for BoardSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl FromStr for BoardSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| "expected WIDTHxHEIGHT, like 18x18".to_owned())?;
+        let parse = |value: &str| {
+            value
+                .parse()
+                .ok()
+                .filter(|value| (Self::MIN..=Self::MAX).contains(value))
+                .ok_or_else(|| {
+                    format!(
+                        "the width and height must be from {} to {}",
+                        Self::MIN,
+                        Self::MAX
+                    )
+                })
+        };
+        Ok(Self {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+/// How many apples should be on the board at the same time.
+#[derive(Resource)]
+struct AppleCount(usize);
+
+/// How many snakes are on the board, each one with its own controls.
+#[derive(Resource)]
+struct PlayerCount(u8);
+
+/// Set when the snakes cover the whole board, which wins the match for every snake on it.
+#[derive(Resource, Clone, Default)]
+struct BoardFilled(bool);
+
+/// Announces who won for a few seconds after the board is filled, instead of a death animation.
+#[derive(Component)]
+struct WinBanner(Timer);
+
+/// The bots steering the last snakes on the board.
+#[derive(Resource)]
+struct Bots {
+    kinds: Vec<BotKind>,
+    /// How long bot programs get to answer every move.
+    timeout: Duration,
+}
+
+/// Keys used to steer a snake.
+struct ControlScheme {
+    up: KeyCode,
+    down: KeyCode,
+    left: KeyCode,
+    right: KeyCode,
+}
+
+const CONTROL_SCHEMES: [ControlScheme; 4] = [
+    ControlScheme {
+        up: KeyCode::Up,
+        down: KeyCode::Down,
+        left: KeyCode::Left,
+        right: KeyCode::Right,
+    },
+    ControlScheme {
+        up: KeyCode::W,
+        down: KeyCode::S,
+        left: KeyCode::A,
+        right: KeyCode::D,
+    },
+    ControlScheme {
+        up: KeyCode::I,
+        down: KeyCode::K,
+        left: KeyCode::J,
+        right: KeyCode::L,
+    },
+    ControlScheme {
+        up: KeyCode::Numpad8,
+        down: KeyCode::Numpad5,
+        left: KeyCode::Numpad4,
+        right: KeyCode::Numpad6,
+    },
+];
+
+/// The player controlling a snake, used to pick its controls, colour and score line.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+struct Player(usize);
+
+impl Player {
+    const fn controls(self) -> &'static ControlScheme {
+        &CONTROL_SCHEMES[self.0]
+    }
+
+    const fn color(self, palette: &Palette) -> Color {
+        palette.players[self.0]
+    }
+}
+
+#[derive(Component, Default)]
+struct Score(u32);
+
+#[derive(Component)]
+struct ScoreText(Player);
+
+/// Directions waiting to be applied to a snake on its next moves.
+#[derive(Component, Clone, Default)]
+struct KeyboardDirection(Queue<SnakeDirection>);
+
+/// Marks a snake that crashed. The rules leave it and its tail alone for the rest of the match, and
+/// only dead snakes play the death animation.
+#[derive(Component)]
+struct Dead;
+
+/// Marks the tail segments of a dead snake while they play the death animation, with the frame
+/// they are at.
+#[derive(Component)]
+struct Dying(usize);
+
+#[derive(Resource)]
+struct TextureAtlasHandle(Handle<TextureAtlas>);
+
+/// Everything needed to draw a sprite from the sheet.
+#[derive(SystemParam)]
+struct Sprites<'w> {
+    atlas: Res<'w, TextureAtlasHandle>,
+    frames: Res<'w, SpriteFrames>,
+    palette: Res<'w, Palette>,
+}
+
+#[derive(Component, Clone)]
+struct Apple {
+    x: i32,
+    y: i32,
+}
+
+/// The kind of fruit an `Apple` is, which decides how it looks and what eating it does.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FruitType {
+    Apple,
+    GoldenApple,
+    Berry,
+    Poison,
+}
+
+impl FruitType {
+    fn sprite(self, frames: &SpriteFrames, palette: &Palette) -> TextureAtlasSprite {
+        let (colors, frames) = (&palette.fruits, &frames.fruits);
+        let (color, frame) = match self {
+            Self::Apple => (colors.apple, frames.apple),
+            Self::GoldenApple => (colors.golden_apple, frames.golden_apple),
+            Self::Berry => (colors.berry, frames.berry),
+            Self::Poison => (colors.poison, frames.poison),
+        };
+        TextureAtlasSprite {
+            color,
+            ..TextureAtlasSprite::new(frame)
+        }
+    }
+
+    /// Points awarded for eating this fruit.
+    const fn score(self) -> u32 {
+        match self {
+            Self::Apple | Self::Berry => 1,
+            Self::Poison => 3,
+            Self::GoldenApple => 5,
+        }
+    }
+
+    /// How many tail segments the snake grows when eating this fruit.
+    /// Negative values take segments away from the tail instead.
+    const fn growth(self) -> isize {
+        match self {
+            Self::Apple | Self::Berry => 1,
+            Self::GoldenApple => 2,
+            Self::Poison => -2,
+        }
+    }
+
+    /// How many steps along the `SpeedCurve` eating this fruit moves the snake,
+    /// negative values slow it down.
+    const fn speed_steps(self) -> i32 {
+        match self {
+            Self::Apple | Self::GoldenApple => 1,
+            Self::Berry => -2,
+            Self::Poison => 0,
+        }
+    }
+}
+
+/// A fruit that shows up once in a while and disappears if it is not eaten in time.
+/// The sooner it is eaten, the more points it is worth.
+#[derive(Component, Clone)]
+struct BonusFruit {
+    x: i32,
+    y: i32,
+    lifetime: Timer,
+}
+
+impl BonusFruit {
+    fn new(x: i32, y: i32) -> Self {
+        Self {
+            x,
+            y,
+            lifetime: Timer::from_seconds(BONUS_FRUIT_LIFETIME, TimerMode::Once),
+        }
+    }
+
+    /// Points awarded for eating the fruit now, scaled by how much of its lifetime is left.
+    fn score(&self) -> u32 {
+        let remaining = self.lifetime.remaining().as_millis();
+        let total = self.lifetime.duration().as_millis().max(1);
+        let score = (u128::from(BONUS_FRUIT_MAX_SCORE) * remaining).div_ceil(total);
+        u32::try_from(score).unwrap_or(BONUS_FRUIT_MAX_SCORE).max(1)
+    }
+}
+
+#[derive(Component, Clone)]
+struct BonusSpawnTimer(Timer);
+
+#[derive(Component)]
+struct Wall {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Component)]
+struct Glass;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnakeDirection {
+    Up,
+    Down,
+    Right,
+    Left,
+}
+
+#[derive(Component)]
+struct Snake {
+    x: i32,
+    y: i32,
+    direction: SnakeDirection,
+    tail: Vec<Entity>,
+}
+
+#[derive(Component, Clone)]
+struct Tail {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Component)]
+struct AnimationTimer(Timer);
+
+#[derive(Component)]
+struct MoveTimer(Timer);
+
+impl Default for MoveTimer {
+    fn default() -> Self {
+        Self(Timer::from_seconds(0.3, TimerMode::Repeating))
+    }
+}
+
+fn setup_resources(
+    mut commands: Commands,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    asset_server: Res<AssetServer>,
+    frames: Res<SpriteFrames>,
+    skin: Option<Res<Skin>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let texture_handle = match skin {
+        Some(skin) => images.add(skin.sheet.clone()),
+        None => asset_server.load("embedded://sprites.png"),
+    };
+    let texture_atlas = TextureAtlas::from_grid(
+        texture_handle,
+        Vec2::new(SPRITE_SIZE, SPRITE_SIZE),
+        frames.columns,
+        frames.rows,
+        None,
+        None,
+    );
+    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+    commands.insert_resource(FrameCount(0));
+    commands.insert_resource(TextureAtlasHandle(texture_atlas_handle));
+    commands.spawn(AnimationTimer(Timer::from_seconds(
+        0.1,
+        TimerMode::Repeating,
+    )));
+}
+
+fn setup_timers(mut commands: Commands) {
+    commands.spawn(MoveTimer::default());
+    commands.spawn(BonusSpawnTimer(Timer::from_seconds(
+        10.0,
+        TimerMode::Repeating,
+    )));
+}
+
+fn setup_hud(mut commands: Commands, player_count: Res<PlayerCount>, palette: Res<Palette>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                left: Val::Px(8.0),
+                flex_direction: FlexDirection::Column,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for player in (0..usize::from(player_count.0)).map(Player) {
+                parent.spawn((
+                    TextBundle::from_section(
+                        score_label(player, 0, player_count.0),
+                        TextStyle {
+                            font_size: 24.0,
+                            color: player.color(&palette),
+                            ..Default::default()
+                        },
+                    ),
+                    ScoreText(player),
+                ));
+            }
+        });
+}
+
+fn score_label(player: Player, score: u32, player_count: u8) -> String {
+    if player_count == 1 {
+        format!("Score: {score}")
+    } else {
+        format!("P{}: {score}", player.0 + 1)
+    }
+}
+
+fn reset_speed(mut speed: ResMut<Speed>) {
+    speed.reset();
+}
+
+fn accelerate_over_time(mut speed: ResMut<Speed>, tick: Res<Tick>) {
+    speed.accelerate(tick.delta.as_secs_f32());
+}
+
+/// Keeps the `MoveTimer` in sync with the current `Speed` and the active effects.
+fn update_move_timer(
+    speed: Res<Speed>,
+    slow_motion_query: Query<(), (With<Snake>, With<SlowMotion>)>,
+    mut move_timer_query: Query<&mut MoveTimer>,
+) {
+    let factor = if slow_motion_query.is_empty() {
+        1.0
+    } else {
+        SLOW_MOTION_FACTOR
+    };
+    let duration = Duration::from_secs_f32(speed.interval * factor);
+    let mut timer = move_timer_query.single_mut();
+    if timer.0.duration() != duration {
+        timer.0.set_duration(duration);
+    }
+}
+
+fn update_score_text(
+    score_query: Query<(&Score, &Player), Changed<Score>>,
+    mut text_query: Query<(&mut Text, &ScoreText)>,
+    player_count: Res<PlayerCount>,
+) {
+    for (score, player) in &score_query {
+        for (mut text, score_text) in &mut text_query {
+            if score_text.0 == *player {
+                text.sections[0].value = score_label(*player, score.0, player_count.0);
+            }
+        }
+    }
+}
+
+fn setup_glass(
+    mut commands: Commands,
+    texture_atlas_handle: Res<TextureAtlasHandle>,
+    frames: Res<SpriteFrames>,
+    board_size: Res<BoardSize>,
+) {
+    let texture_atlas_handle = &texture_atlas_handle.0;
+    let (table_width, table_height) = board_size.table();
+    (0..table_width * table_height).for_each(|i| {
+        let x = i % table_width;
+        let y = i / table_width;
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle.clone(),
+                transform: Transform::from_translation(Vec3::new(
+                    ((x) as f32) * SPRITE_SIZE,
+                    ((y) as f32) * SPRITE_SIZE,
+                    -100.0,
+                )),
+                sprite: TextureAtlasSprite::new(frames.glass),
+                ..Default::default()
+            },
+            Glass,
+        ));
+    });
+}
+
+fn setup_wall(
+    mut commands: Commands,
+    texture_atlas_handle: Res<TextureAtlasHandle>,
+    frames: Res<SpriteFrames>,
+    board_size: Res<BoardSize>,
+) {
+    let texture_atlas_handle = &texture_atlas_handle.0;
+    let (wall_width, wall_height) = (board_size.width + 2, board_size.height + 2);
+    (0..wall_width * wall_height).for_each(|i| {
+        let x = i % wall_width;
+        let y = i / wall_width;
+        if x == 0 || x == wall_width - 1 || y == 0 || y == wall_height - 1 {
+            commands.spawn((
+                Wall { x: x + 1, y: y + 1 },
+                SpriteSheetBundle {
+                    texture_atlas: texture_atlas_handle.clone(),
+                    transform: Transform::from_translation(Vec3::new(
+                        ((x + 1) as f32) * SPRITE_SIZE,
+                        ((y + 1) as f32) * SPRITE_SIZE,
+                        0.0,
+                    )),
+                    sprite: if x == 0 {
+                        if y == 0 {
+                            TextureAtlasSprite::new(frames.wall.bottom_left)
+                        } else if y == wall_height - 1 {
+                            TextureAtlasSprite::new(frames.wall.top_left)
+                        } else {
+                            TextureAtlasSprite::new(frames.wall.left)
+                        }
+                    } else if x == wall_width - 1 {
+                        if y == 0 {
+                            TextureAtlasSprite::new(frames.wall.bottom_right)
+                        } else if y == wall_height - 1 {
+                            TextureAtlasSprite::new(frames.wall.top_right)
+                        } else {
+                            TextureAtlasSprite::new(frames.wall.right)
+                        }
+                    } else {
+                        TextureAtlasSprite::new(frames.wall.top_bottom)
+                    },
+                    ..Default::default()
+                },
+            ));
+        }
+    });
+}
+
+/// Makes bonus fruits blink when they are about to disappear.
+fn blink_bonus_fruit(mut bonus_query: Query<(&BonusFruit, &mut Visibility)>, time: Res<Time>) {
+    for (bonus, mut visibility) in &mut bonus_query {
+        if bonus.lifetime.remaining_secs() < BONUS_FRUIT_BLINK_TIME {
+            *visibility = if (time.elapsed().as_millis() / 150) % 2 == 0 {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
+
+fn setup_snake(
+    mut commands: Commands,
+    texture_atlas_handle: Res<TextureAtlasHandle>,
+    palette: Res<Palette>,
+    player_count: Res<PlayerCount>,
+    bots: Res<Bots>,
+    board_size: Res<BoardSize>,
+) {
+    let texture_atlas_handle = &texture_atlas_handle.0;
+    let first_bot = usize::from(player_count.0).saturating_sub(bots.kinds.len());
+
+    for (player, row) in (0..usize::from(player_count.0)).map(Player).zip(1..) {
+        // Spread the snakes over the rows of the board, every other one heading left.
+        let y = 2 + row * board_size.height / (i32::from(player_count.0) + 1);
+        let (direction, step) = if player.0 % 2 == 0 {
+            (SnakeDirection::Right, -1)
+        } else {
+            (SnakeDirection::Left, 1)
+        };
+        let tail_entities = (1..=3)
+            .map(|i| {
+                let texture_atlas_handle = texture_atlas_handle.clone();
+                commands
+                    .spawn((
+                        Tail {
+                            x: step * i + board_size.width / 2 + 2,
+                            y,
+                        },
+                        SpriteSheetBundle {
+                            texture_atlas: texture_atlas_handle,
+                            transform: Transform::from_translation(Vec3 {
+                                x: 0.0,
+                                y: 0.0,
+                                z: -(i as f32),
+                            }),
+                            sprite: TextureAtlasSprite {
+                                color: player.color(&palette),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                    ))
+                    .id()
+            })
+            .collect();
+
+        let mut snake = commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle.clone(),
+                transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                sprite: TextureAtlasSprite {
+                    color: player.color(&palette),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Snake {
+                x: board_size.width / 2 + 2,
+                y,
+                direction,
+                tail: tail_entities,
+            },
+            player,
+            Score::default(),
+            KeyboardDirection::default(),
+        ));
+        if let Some(kind) = player.0.checked_sub(first_bot).map(|bot| &bots.kinds[bot]) {
+            snake.insert(Bot::new(kind.start(bots.timeout)));
+        }
+    }
+}
+
+/// Queues the directions pressed by every player on the snake they control.
+fn read_keyboard_direction(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut snake_query: Query<(&mut KeyboardDirection, &Player), Without<Bot>>,
+) {
+    for (mut keyboard_direction, player) in &mut snake_query {
+        queue_pressed_directions(
+            &keyboard_input,
+            player.controls(),
+            &mut keyboard_direction.0,
+        );
+    }
+}
+
+/// Pushes the directions pressed this frame, ignoring presses that would turn the snake back on
+/// itself or repeat the direction at the front of the queue.
+fn queue_pressed_directions(
+    keyboard_input: &Input<KeyCode>,
+    controls: &ControlScheme,
+    queue: &mut Queue<SnakeDirection>,
+) {
+    if keyboard_input.just_pressed(controls.up)
+        && queue.peek() != Some(SnakeDirection::Down)
+        && queue.peek() != Some(SnakeDirection::Up)
+    {
+        queue.push(SnakeDirection::Up);
+    }
+    if keyboard_input.just_pressed(controls.down)
+        && queue.peek() != Some(SnakeDirection::Down)
+        && queue.peek() != Some(SnakeDirection::Up)
+    {
+        queue.push(SnakeDirection::Down);
+    }
+    if keyboard_input.just_pressed(controls.left)
+        && queue.peek() != Some(SnakeDirection::Left)
+        && queue.peek() != Some(SnakeDirection::Right)
+    {
+        queue.push(SnakeDirection::Left);
+    }
+    if keyboard_input.just_pressed(controls.right)
+        && queue.peek() != Some(SnakeDirection::Left)
+        && queue.peek() != Some(SnakeDirection::Right)
+    {
+        queue.push(SnakeDirection::Right);
+    }
+}
+
+fn draw_snake_sprites(
+    frames: Res<SpriteFrames>,
+    mut snake_query: Query<(&Snake, &mut Transform, &mut TextureAtlasSprite)>,
+    mut tail_query: Query<(&Tail, &mut Transform, &mut TextureAtlasSprite), Without<Snake>>,
+) {
+    for (snake, mut transform, mut sprite) in &mut snake_query {
+        transform.translation.x = (snake.x as f32) * SPRITE_SIZE;
+        transform.translation.y = (snake.y as f32) * SPRITE_SIZE;
+        sprite.index = frames.head.facing(snake.direction);
+        let mut prev_tail_x = snake.x;
+        let mut prev_tail_y = snake.y;
+        let entities = &snake.tail;
+        for i in 0..entities.len() {
+            let (next_tail_x, next_tail_y) = if i + 1 < entities.len() {
+                if let Ok((next_tail, _, _)) = tail_query.get(entities[i + 1]) {
+                    (next_tail.x, next_tail.y)
+                } else {
+                    (0, 0)
+                }
+            } else {
+                (0, 0)
+            };
+
+            if let Ok((tail, mut transform, mut sprite)) = tail_query.get_mut(entities[i]) {
+                transform.translation.x = (tail.x as f32) * SPRITE_SIZE;
+                transform.translation.y = (tail.y as f32) * SPRITE_SIZE;
+                if i == entities.len() - 1 {
+                    match (prev_tail_x - tail.x, prev_tail_y - tail.y) {
+                        (0, 1) => sprite.index = frames.tail.end_up,
+                        (0, -1) => sprite.index = frames.tail.end_down,
+                        (1, 0) => sprite.index = frames.tail.end_right,
+                        (-1, 0) => sprite.index = frames.tail.end_left,
+                        _ => (),
+                    }
+                } else {
+                    match (
+                        prev_tail_x - tail.x,
+                        prev_tail_y - tail.y,
+                        next_tail_x - tail.x,
+                        next_tail_y - tail.y,
+                    ) {
+                        (0, 1, 0, -1) | (0, -1, 0, 1) => {
+                            sprite.index = frames.tail.vertical;
+                        }
+                        (1, 0, -1, 0) | (-1, 0, 1, 0) => {
+                            sprite.index = frames.tail.horizontal;
+                        }
+                        (1, 0, 0, 1) | (0, 1, 1, 0) => sprite.index = frames.tail.up_right,
+                        (-1, 0, 0, 1) | (0, 1, -1, 0) => sprite.index = frames.tail.up_left,
+                        (1, 0, 0, -1) | (0, -1, 1, 0) => {
+                            sprite.index = frames.tail.down_right;
+                        }
+                        (-1, 0, 0, -1) | (0, -1, -1, 0) => {
+                            sprite.index = frames.tail.down_left;
+                        }
+                        _ => (),
+                    }
+                }
+                prev_tail_x = tail.x;
+                prev_tail_y = tail.y;
+            }
+        }
+    }
+}
+
+/// Hides the snakes that crashed while the others play on, and shows them again if a rollback
+/// brings them back to life.
+fn hide_dead_snakes(
+    snake_query: Query<(Entity, &Snake, Has<Dead>)>,
+    mut visibility_query: Query<&mut Visibility>,
+) {
+    for (entity, snake, dead) in &snake_query {
+        let shown = if dead {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        let mut parts = visibility_query.iter_many_mut(std::iter::once(&entity).chain(&snake.tail));
+        while let Some(mut visibility) = parts.fetch_next() {
+            if *visibility != shown {
+                *visibility = shown;
+            }
+        }
+    }
+}
+
+/// Where the head or the end of a tail slides from and to while the `MoveTimer` runs.
+#[derive(Component)]
+struct Glide {
+    from: (i32, i32),
+    to: (i32, i32),
+}
+
+/// Moves the heads and the ends of the tails part of the way from their previous tile, as far as
+/// the `MoveTimer` got. The rest of the snake stays on its tile, and so does the logical grid.
+fn glide_snake_sprites(
+    mut commands: Commands,
+    move_timer_query: Query<&MoveTimer>,
+    snake_query: Query<(Entity, &Snake)>,
+    tail_query: Query<&Tail>,
+    mut glide_query: Query<(&mut Glide, &mut Transform)>,
+) {
+    let fraction = move_timer_query.single().0.percent();
+    let ends = snake_query.iter().flat_map(|(entity, snake)| {
+        let tail_end = snake.tail.last().and_then(|tail_entity| {
+            let tail = tail_query.get(*tail_entity).ok()?;
+            Some((*tail_entity, (tail.x, tail.y)))
+        });
+        std::iter::once((entity, (snake.x, snake.y))).chain(tail_end)
+    });
+    for (entity, position) in ends {
+        let Ok((mut glide, mut transform)) = glide_query.get_mut(entity) else {
+            commands.entity(entity).insert(Glide {
+                from: position,
+                to: position,
+            });
+            continue;
+        };
+        if glide.to != position {
+            // Only slide one tile, anything further is drawn where it ended up.
+            let (dx, dy) = (position.0 - glide.to.0, position.1 - glide.to.1);
+            glide.from = if dx.abs() + dy.abs() == 1 {
+                glide.to
+            } else {
+                position
+            };
+            glide.to = position;
+        }
+        let (from, to) = (glide.from, glide.to);
+        transform.translation.x =
+            ((to.0 - from.0) as f32).mul_add(fraction, from.0 as f32) * SPRITE_SIZE;
+        transform.translation.y =
+            ((to.1 - from.1) as f32).mul_add(fraction, from.1 as f32) * SPRITE_SIZE;
+    }
+}
+
+fn draw_apple_sprite(mut apple_query: Query<(&Apple, &mut Transform)>) {
+    for (apple, mut transform) in &mut apple_query {
+        transform.translation.x = (apple.x as f32) * SPRITE_SIZE;
+        transform.translation.y = (apple.y as f32) * SPRITE_SIZE;
+    }
+}
+
+/// Shows how a match ended, with the dying snakes falling apart or a banner when the board was
+/// filled, then starts the next one. Snakes crashing before that disappear from the board.
+struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            hide_dead_snakes
+                .after(run_game_ticks)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            (setup_death_animation, show_win_banner),
+        )
+        .add_systems(
+            Update,
+            (death_animation, hide_win_banner).run_if(in_state(GameState::GameOver)),
+        );
+    }
+}
+
+fn reset_board_filled(mut board_filled: ResMut<BoardFilled>) {
+    board_filled.0 = false;
+}
+
+fn show_win_banner(
+    mut commands: Commands,
+    board_filled: Res<BoardFilled>,
+    snake_query: Query<&Player, (With<Snake>, Without<Dead>)>,
+    player_count: Res<PlayerCount>,
+    palette: Res<Palette>,
+) {
+    if !board_filled.0 {
+        return;
+    }
+    let winners: Vec<Player> = snake_query.iter().copied().collect();
+    let (label, color) = match winners[..] {
+        [winner] if player_count.0 > 1 => {
+            (format!("P{} wins!", winner.0 + 1), winner.color(&palette))
+        }
+        [winner] => ("You win!".to_owned(), winner.color(&palette)),
+        _ => ("The board is full!".to_owned(), Color::WHITE),
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            WinBanner(Timer::from_seconds(WIN_BANNER_SECONDS, TimerMode::Once)),
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: 48.0,
+                    color,
+                    ..Default::default()
+                },
+            ));
+        });
+}
+
+/// Starts the next match once the banner has been up long enough.
+fn hide_win_banner(
+    mut commands: Commands,
+    mut banner_query: Query<(&mut WinBanner, Entity)>,
+    mut game_state: ResMut<NextState<GameState>>,
+    time: Res<Time>,
+) {
+    for (mut banner, entity) in &mut banner_query {
+        if banner.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            game_state.set(GameState::Playing);
+        }
+    }
+}
+
+fn setup_death_animation(
+    frames: Res<SpriteFrames>,
+    mut tail_query: Query<&mut TextureAtlasSprite, With<Tail>>,
+    snake_query: Query<(&Snake, Entity), With<Dead>>,
+    mut commands: Commands,
+) {
+    for (snake, snake_entity) in snake_query.iter() {
+        for tail_entity in &snake.tail {
+            if let (Ok(mut sprite), Some(frame)) =
+                (tail_query.get_mut(*tail_entity), frames.death.first())
+            {
+                sprite.index = *frame;
+            }
+            commands.entity(*tail_entity).insert(Dying(0));
+        }
+        commands.entity(snake_entity).despawn();
+    }
+}
+
+fn death_animation(
+    frames: Res<SpriteFrames>,
+    mut tail_query: Query<(&mut TextureAtlasSprite, &mut Dying)>,
+    mut animation_timer_query: Query<&mut AnimationTimer>,
+    banner_query: Query<(), With<WinBanner>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    time: Res<Time>,
+) {
+    let mut timer = animation_timer_query.single_mut();
+    if timer.0.tick(time.delta()).just_finished() {
+        let mut finished = true;
+        for (mut sprite, mut dying) in &mut tail_query {
+            if let Some(frame) = frames.death.get(dying.0 + 1) {
+                sprite.index = *frame;
+                dying.0 += 1;
+                finished = false;
+            }
+        }
+        // A snake that lost its whole tail has nothing left to animate, and a won match waits for
+        // its banner instead.
+        if finished && banner_query.is_empty() {
+            game_state.set(GameState::Playing);
+        }
+    }
+}
+
+fn clear_game_scene(
+    wall_query: Query<Entity, With<Wall>>,
+    apple_query: Query<Entity, With<Apple>>,
+    bonus_query: Query<Entity, With<BonusFruit>>,
+    glass_query: Query<Entity, With<Glass>>,
+    tail_query: Query<Entity, With<Tail>>,
+    snake_query: Query<Entity, With<Snake>>,
+    mut commands: Commands,
+) {
+    for wall_entity in wall_query.iter() {
+        commands.entity(wall_entity).despawn();
+    }
+    for apple_entity in apple_query.iter() {
+        commands.entity(apple_entity).despawn();
+    }
+    for bonus_entity in bonus_query.iter() {
+        commands.entity(bonus_entity).despawn();
+    }
+    for glass_entity in glass_query.iter() {
+        commands.entity(glass_entity).despawn();
+    }
+    for tail_entity in tail_query.iter() {
+        commands.entity(tail_entity).despawn();
+    }
+    for snake_entity in snake_query.iter() {
+        commands.entity(snake_entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use headless::{rules_app, Match};
+
+    fn same(a: f32, b: f32) -> bool {
+        (a - b).abs() < f32::EPSILON
+    }
+
+    #[test]
+    fn heads_glide_one_tile_and_snap_further() {
+        let mut world = World::new();
+        let mut timer = Timer::from_seconds(0.2, TimerMode::Repeating);
+        timer.set_elapsed(Duration::from_secs_f32(0.1));
+        world.spawn(MoveTimer(timer));
+        let head = world
+            .spawn((
+                Snake {
+                    x: 5,
+                    y: 5,
+                    direction: SnakeDirection::Right,
+                    tail: Vec::new(),
+                },
+                Transform::default(),
+            ))
+            .id();
+        let mut drawn_after_moving_to = |(x, y)| {
+            let mut snake = world.get_mut::<Snake>(head).unwrap();
+            (snake.x, snake.y) = (x, y);
+            world.run_system_once(glide_snake_sprites);
+            let translation = world.get::<Transform>(head).unwrap().translation;
+            (translation.x / SPRITE_SIZE, translation.y / SPRITE_SIZE)
+        };
+
+        drawn_after_moving_to((5, 5));
+        assert_eq!(drawn_after_moving_to((6, 5)), (5.5, 5.0));
+        assert_eq!(drawn_after_moving_to((6, 5)), (5.5, 5.0));
+        // Wrapping around the board or respawning jumps too far to slide.
+        assert_eq!(drawn_after_moving_to((2, 5)), (2.0, 5.0));
+        assert_eq!(drawn_after_moving_to((2, 6)), (2.0, 5.5));
+        assert_eq!(drawn_after_moving_to((9, 9)), (9.0, 9.0));
+    }
+
+    #[test]
+    fn the_speed_stays_on_its_curve() {
+        let difficulties = [
+            Difficulty::Easy,
+            Difficulty::Normal,
+            Difficulty::Hard,
+            Difficulty::Insane,
+        ];
+        for difficulty in difficulties {
+            let curve = difficulty.speed_curve();
+            let mut speed = Speed::new(curve);
+            speed.step(-5);
+            assert!(same(speed.interval, curve.start_interval));
+            speed.step(1);
+            assert!(speed.interval < curve.start_interval);
+            speed.step(1000);
+            assert!(same(speed.interval, curve.min_interval));
+            speed.reset();
+            speed.accelerate(10_000.0);
+            let floor = if curve.time_factor.is_some() {
+                curve.min_interval
+            } else {
+                curve.start_interval
+            };
+            assert!(same(speed.interval, floor), "{difficulty:?}");
+        }
+        let starts = difficulties.map(|difficulty| difficulty.speed_curve().start_interval);
+        assert!(starts.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn restarting_goes_back_to_the_starting_speed() {
+        let mut game = Match::new(MatchConfig {
+            difficulty: Difficulty::Hard,
+            ..MatchConfig::default()
+        });
+        let start = game.tick_interval();
+        // Nobody steers, so the snake speeds up until it runs into the wall.
+        while game.step() {}
+        assert!(game.tick_interval() < start);
+        game.restart();
+        assert_eq!(game.tick_interval(), start);
+    }
+
+    #[test]
+    fn bonus_fruits_blink_when_about_to_disappear() {
+        let mut app = rules_app(MatchConfig {
+            apples: Some(0),
+            ..MatchConfig::default()
+        });
+        let fresh = app
+            .world
+            .spawn((BonusFruit::new(3, 3), Visibility::Inherited))
+            .id();
+        let mut old = BonusFruit::new(4, 4);
+        old.lifetime.tick(Duration::from_secs_f32(
+            BONUS_FRUIT_LIFETIME - BONUS_FRUIT_BLINK_TIME / 2.0,
+        ));
+        let old = app.world.spawn((old, Visibility::Inherited)).id();
+        let visibility = |app: &mut App, elapsed: u64| {
+            let world = &mut app.world;
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_millis(elapsed));
+            world.run_system_once(blink_bonus_fruit);
+            [fresh, old].map(|entity| *world.get::<Visibility>(entity).unwrap())
+        };
+        assert_eq!(
+            visibility(&mut app, 150),
+            [Visibility::Inherited, Visibility::Hidden]
+        );
+        assert_eq!(
+            visibility(&mut app, 150),
+            [Visibility::Inherited, Visibility::Inherited]
+        );
+    }
+}
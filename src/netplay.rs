@@ -0,0 +1,1012 @@
+//! Online versus play over UDP.
+//!
+//! Both peers simulate the whole game and only exchange the direction their player picked for
+//! every tick. Since the game rules only depend on those inputs and the shared seed, both games
+//! play out the same. Every peer also hashes the state of the game after each confirmed tick and
+//! sends the hashes along, so a mismatch shows up as a desync instead of two silently different
+//! games.
+//!
+//! With [`Netcode::Lockstep`] a tick is only simulated once the inputs of both players for it are
+//! known, stalling the game whenever the inputs of the opponent are late. With
+//! [`Netcode::Rollback`] the game keeps going by predicting the opponent does not turn, saving a
+//! [`Snapshot`] before every predicted tick. When the real input arrives and differs, the game is
+//! restored to the snapshot and the ticks after it are simulated again.
+//!
+//! Inputs are sent `input_delay` ticks ahead of the tick they are applied on, which hides the
+//! latency between the peers as long as it is shorter than the delay.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, VecDeque},
+    hash::{Hash, Hasher},
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+mod discovery;
+
+use bevy::{ecs::system::RunSystemOnce, prelude::*};
+
+use clap::ValueEnum;
+
+use rand::prelude::*;
+
+use crate::{
+    queue_pressed_directions,
+    rules::{run_game_tick, run_game_ticks, Board, Tick, TickInputs},
+    snapshot::Snapshot,
+    Args, BoardSize, Dead, Difficulty, FruitType, GameMode, GameState, MoveTimer, Player, Queue,
+    Score, Snake, SnakeDirection, Speed, CONTROL_SCHEMES,
+};
+use discovery::{Announcement, Announcer};
+pub use discovery::{LobbyPlugin, DISCOVERY_PORT};
+
+const MAGIC: &[u8; 4] = b"SNK1";
+const MAX_PACKET_SIZE: usize = 1024;
+/// Most inputs sent in a single packet, older ones are resent once these are acknowledged.
+const MAX_SENT_INPUTS: u64 = 64;
+/// How many of the latest state hashes are sent in every packet.
+const SENT_HASHES: usize = 8;
+/// How many ticks rollback may run ahead of the last tick with known inputs.
+const ROLLBACK_WINDOW: u64 = 8;
+const HANDSHAKE_RETRY: Duration = Duration::from_millis(500);
+/// How long joining waits for the host to answer before giving up.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the game has to wait for the opponent before the HUD says so.
+const STALL_WARNING: Duration = Duration::from_secs(1);
+
+pub struct NetplayPlugin;
+
+impl Plugin for NetplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_netplay_hud)
+            .add_systems(
+                Update,
+                (
+                    receive_packets,
+                    read_local_input,
+                    advance_session.run_if(in_state(GameState::Playing)),
+                    send_packets,
+                )
+                    .chain()
+                    .before(run_game_ticks)
+                    .run_if(resource_exists::<Session>()),
+            )
+            .add_systems(
+                Update,
+                update_netplay_hud.run_if(resource_exists::<Session>()),
+            );
+    }
+}
+
+/// How the game deals with inputs of the opponent that did not arrive yet.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Netcode {
+    /// Wait for them
+    Lockstep,
+    /// Predict them and correct the game once they arrive
+    Rollback,
+}
+
+/// The settings the host picked for the match, sent to the client when it joins.
+#[derive(Clone, Copy, Debug)]
+struct MatchSettings {
+    seed: u64,
+    mode: GameMode,
+    difficulty: Difficulty,
+    apples: u16,
+    board: BoardSize,
+    input_delay: u8,
+    netcode: Netcode,
+}
+
+impl MatchSettings {
+    fn from_args(args: &Args) -> Self {
+        let apples = args.apples.unwrap_or_else(|| args.mode.apple_count());
+        Self {
+            seed: args.seed.unwrap_or_else(random),
+            mode: args.mode,
+            difficulty: args.difficulty,
+            apples: u16::try_from(apples).unwrap_or(u16::MAX),
+            board: args.board,
+            input_delay: u8::try_from(args.input_delay).unwrap_or(u8::MAX),
+            netcode: args.netcode,
+        }
+    }
+
+    /// Overrides the local settings with the ones of the match.
+    fn apply(self, args: &mut Args) {
+        args.seed = Some(self.seed);
+        args.mode = self.mode;
+        args.difficulty = self.difficulty;
+        args.apples = Some(usize::from(self.apples));
+        args.board = self.board;
+        args.input_delay = u64::from(self.input_delay);
+        args.netcode = self.netcode;
+        args.players = 2;
+        args.bots.clear();
+        args.bot_command.clear();
+        args.bot_url.clear();
+        args.bot_genome.clear();
+    }
+}
+
+enum Message {
+    /// Sent by the client until the host welcomes it.
+    Hello,
+    Welcome(MatchSettings),
+    Inputs {
+        /// Tick of the first direction in `directions`.
+        first_tick: u64,
+        directions: Vec<Option<SnakeDirection>>,
+        /// The sender has every input of the receiver before this tick.
+        received: u64,
+        /// State hashes of the latest confirmed ticks of the sender.
+        hashes: Vec<(u64, u64)>,
+    },
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        match self {
+            Self::Hello => bytes.push(0),
+            Self::Welcome(settings) => {
+                bytes.push(1);
+                bytes.extend(settings.seed.to_le_bytes());
+                bytes.push(variant_index(&settings.mode));
+                bytes.push(variant_index(&settings.difficulty));
+                bytes.extend(settings.apples.to_le_bytes());
+                bytes.extend(
+                    [settings.board.width, settings.board.height]
+                        .map(|size| u8::try_from(size).unwrap_or(u8::MAX)),
+                );
+                bytes.push(settings.input_delay);
+                bytes.push(variant_index(&settings.netcode));
+            }
+            Self::Inputs {
+                first_tick,
+                directions,
+                received,
+                hashes,
+            } => {
+                bytes.push(2);
+                bytes.extend(first_tick.to_le_bytes());
+                bytes.extend(received.to_le_bytes());
+                bytes.push(u8::try_from(directions.len()).unwrap_or(u8::MAX));
+                bytes.extend(
+                    directions
+                        .iter()
+                        .take(usize::from(u8::MAX))
+                        .map(|d| direction_to_byte(*d)),
+                );
+                bytes.push(u8::try_from(hashes.len()).unwrap_or(u8::MAX));
+                for (tick, hash) in hashes.iter().take(usize::from(u8::MAX)) {
+                    bytes.extend(tick.to_le_bytes());
+                    bytes.extend(hash.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Reads a message, returning `None` for anything that is not a valid packet of this game.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes.strip_prefix(MAGIC)?);
+        match reader.u8()? {
+            0 => Some(Self::Hello),
+            1 => Some(Self::Welcome(MatchSettings {
+                seed: reader.u64()?,
+                mode: *GameMode::value_variants().get(usize::from(reader.u8()?))?,
+                difficulty: *Difficulty::value_variants().get(usize::from(reader.u8()?))?,
+                apples: u16::from_le_bytes([reader.u8()?, reader.u8()?]),
+                board: BoardSize {
+                    width: i32::from(reader.u8()?),
+                    height: i32::from(reader.u8()?),
+                },
+                input_delay: reader.u8()?,
+                netcode: *Netcode::value_variants().get(usize::from(reader.u8()?))?,
+            })),
+            2 => {
+                let first_tick = reader.u64()?;
+                let received = reader.u64()?;
+                let directions = (0..reader.u8()?)
+                    .map(|_| reader.u8().map(direction_from_byte))
+                    .collect::<Option<_>>()?;
+                let hashes = (0..reader.u8()?)
+                    .map(|_| Some((reader.u64()?, reader.u64()?)))
+                    .collect::<Option<_>>()?;
+                Some(Self::Inputs {
+                    first_tick,
+                    directions,
+                    received,
+                    hashes,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let (bytes, rest) = self.0.split_first_chunk::<8>()?;
+        self.0 = rest;
+        Some(u64::from_le_bytes(*bytes))
+    }
+}
+
+fn variant_index<T: ValueEnum + PartialEq>(value: &T) -> u8 {
+    let index = T::value_variants()
+        .iter()
+        .position(|variant| variant == value)
+        .unwrap_or_default();
+    u8::try_from(index).unwrap_or_default()
+}
+
+const fn direction_to_byte(direction: Option<SnakeDirection>) -> u8 {
+    match direction {
+        None => 0,
+        Some(SnakeDirection::Up) => 1,
+        Some(SnakeDirection::Down) => 2,
+        Some(SnakeDirection::Left) => 3,
+        Some(SnakeDirection::Right) => 4,
+    }
+}
+
+const fn direction_from_byte(byte: u8) -> Option<SnakeDirection> {
+    match byte {
+        1 => Some(SnakeDirection::Up),
+        2 => Some(SnakeDirection::Down),
+        3 => Some(SnakeDirection::Left),
+        4 => Some(SnakeDirection::Right),
+        _ => None,
+    }
+}
+
+/// Carries packets between the two peers.
+trait Transport: Send + Sync {
+    fn send(&mut self, packet: Vec<u8>) -> io::Result<()>;
+
+    /// Returns the next packet of the peer, or `None` if there is none waiting.
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: Vec<u8>) -> io::Result<()> {
+        self.socket.send_to(&packet, self.peer).map(drop)
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, peer)) if peer == self.peer => {
+                    return Ok(Some(buffer[..length].to_vec()));
+                }
+                Ok(_) => {}
+                // Windows reports an earlier packet the peer did not take yet.
+                Err(error) if error.kind() == ErrorKind::ConnectionReset => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Holds every outgoing packet back for a while, to try the netcode on a slow connection while
+/// both peers run on the same machine.
+struct LatencyTransport<T> {
+    inner: T,
+    latency: Duration,
+    queued: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl<T: Transport> LatencyTransport<T> {
+    fn flush(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while self.queued.front().is_some_and(|(due, _)| *due <= now) {
+            if let Some((_, packet)) = self.queued.pop_front() {
+                self.inner.send(packet)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for LatencyTransport<T> {
+    fn send(&mut self, packet: Vec<u8>) -> io::Result<()> {
+        self.queued
+            .push_back((Instant::now() + self.latency, packet));
+        self.flush()
+    }
+
+    fn receive(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.flush()?;
+        self.inner.receive()
+    }
+}
+
+/// The state of an online match.
+#[derive(Resource)]
+pub struct Session {
+    transport: Box<dyn Transport>,
+    settings: MatchSettings,
+    local_player: Player,
+    remote_player: Player,
+    input_delay: u64,
+    /// How many ticks may be simulated before the inputs of the opponent for them are known.
+    max_prediction: u64,
+    /// Directions pressed locally that were not assigned to a tick yet.
+    pending: Queue<SnakeDirection>,
+    local_inputs: BTreeMap<u64, Option<SnakeDirection>>,
+    remote_inputs: BTreeMap<u64, Option<SnakeDirection>>,
+    /// Every remote input before this tick has been received.
+    remote_received: u64,
+    /// Every local input before this tick has been received by the peer.
+    peer_received: u64,
+    /// The remote direction assumed for every tick simulated without knowing it.
+    predicted: BTreeMap<u64, Option<SnakeDirection>>,
+    /// The state before every predicted tick.
+    snapshots: BTreeMap<u64, Snapshot>,
+    /// The hash of the state after each simulated tick, until the tick is confirmed.
+    tick_hashes: BTreeMap<u64, u64>,
+    local_hashes: BTreeMap<u64, u64>,
+    remote_hashes: BTreeMap<u64, u64>,
+    /// The `MoveTimer` finished and the next tick should run as soon as possible.
+    due: bool,
+    /// How long the game has been waiting for inputs of the opponent.
+    stalled: Duration,
+    /// The first tick where the state of both peers differed.
+    desync: Option<u64>,
+}
+
+impl Session {
+    /// Hosts a match on `port`, announcing it on the local network until an opponent joins.
+    pub fn host(port: u16, args: &mut Args) -> io::Result<Self> {
+        let settings = MatchSettings::from_args(args);
+        settings.apply(args);
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(discovery::ANNOUNCE_INTERVAL))?;
+        let mut announcer = Some(Announcer::new(
+            args.announce_to,
+            &Announcement::new(&args.name, port, args.mode, args.board),
+        )?);
+        info!("Waiting for an opponent on port {port}...");
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let peer = loop {
+            if let Some(Err(error)) = announcer.as_mut().map(Announcer::announce) {
+                warn!("Could not announce the match on the local network: {error}");
+                announcer = None;
+            }
+            match socket.recv_from(&mut buffer) {
+                Ok((length, peer)) => {
+                    if matches!(Message::decode(&buffer[..length]), Some(Message::Hello)) {
+                        break peer;
+                    }
+                }
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionReset
+                    ) => {}
+                Err(error) => return Err(error),
+            }
+        };
+        info!("{peer} joined the match");
+        socket.send_to(&Message::Welcome(settings).encode(), peer)?;
+        Self::new(socket, peer, settings, args, (Player(0), Player(1)))
+    }
+
+    /// Joins the match hosted at `address`, blocking until the host answers or
+    /// `HANDSHAKE_TIMEOUT` passes.
+    pub fn connect(address: SocketAddr, args: &mut Args) -> io::Result<Self> {
+        let socket = UdpSocket::bind(if address.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.set_read_timeout(Some(HANDSHAKE_RETRY))?;
+        info!("Joining the match at {address}...");
+        let started = Instant::now();
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let settings = loop {
+            if started.elapsed() > HANDSHAKE_TIMEOUT {
+                return Err(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "the host did not answer",
+                ));
+            }
+            socket.send_to(&Message::Hello.encode(), address)?;
+            match socket.recv_from(&mut buffer) {
+                Ok((length, peer)) if peer == address => {
+                    if let Some(Message::Welcome(settings)) = Message::decode(&buffer[..length]) {
+                        break settings;
+                    }
+                }
+                Ok(_) => {}
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionReset
+                    ) => {}
+                Err(error) => return Err(error),
+            }
+        };
+        settings.apply(args);
+        Self::new(socket, address, settings, args, (Player(1), Player(0)))
+    }
+
+    fn new(
+        socket: UdpSocket,
+        peer: SocketAddr,
+        settings: MatchSettings,
+        args: &Args,
+        (local_player, remote_player): (Player, Player),
+    ) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let udp = UdpTransport { socket, peer };
+        let transport: Box<dyn Transport> = if args.simulate_latency == 0 {
+            Box::new(udp)
+        } else {
+            Box::new(LatencyTransport {
+                inner: udp,
+                latency: Duration::from_millis(args.simulate_latency),
+                queued: VecDeque::new(),
+            })
+        };
+        let input_delay = u64::from(settings.input_delay);
+        Ok(Self {
+            transport,
+            settings,
+            local_player,
+            remote_player,
+            input_delay,
+            max_prediction: match settings.netcode {
+                Netcode::Lockstep => 0,
+                Netcode::Rollback => ROLLBACK_WINDOW,
+            },
+            pending: Queue::default(),
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            remote_received: input_delay,
+            peer_received: input_delay,
+            predicted: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
+            tick_hashes: BTreeMap::new(),
+            local_hashes: BTreeMap::new(),
+            remote_hashes: BTreeMap::new(),
+            due: false,
+            stalled: Duration::ZERO,
+            desync: None,
+        })
+    }
+
+    fn receive(&mut self) {
+        loop {
+            let packet = match self.transport.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return,
+                Err(error) => {
+                    warn!("Could not receive from the opponent: {error}");
+                    return;
+                }
+            };
+            match Message::decode(&packet) {
+                Some(Message::Hello) => {
+                    // The welcome got lost, the client is still knocking.
+                    self.send(&Message::Welcome(self.settings));
+                }
+                Some(Message::Inputs {
+                    first_tick,
+                    directions,
+                    received,
+                    hashes,
+                }) => {
+                    for (tick, direction) in (first_tick..).zip(directions) {
+                        if tick >= self.remote_received {
+                            self.remote_inputs.insert(tick, direction);
+                        }
+                    }
+                    while self.remote_inputs.contains_key(&self.remote_received) {
+                        self.remote_received += 1;
+                    }
+                    self.peer_received = self.peer_received.max(received);
+                    for (tick, hash) in hashes {
+                        self.remote_hashes.insert(tick, hash);
+                        self.check_hash(tick);
+                    }
+                }
+                Some(Message::Welcome(_)) | None => (),
+            }
+        }
+    }
+
+    fn send(&mut self, message: &Message) {
+        if let Err(error) = self.transport.send(message.encode()) {
+            warn!("Could not send to the opponent: {error}");
+        }
+    }
+
+    /// Sends every local input the peer has not received yet, along with the latest hashes.
+    fn send_inputs(&mut self) {
+        let first_tick = self.peer_received;
+        let directions = self
+            .local_inputs
+            .range(first_tick..first_tick + MAX_SENT_INPUTS)
+            .map(|(_, direction)| *direction)
+            .collect();
+        let hashes = self
+            .local_hashes
+            .iter()
+            .rev()
+            .take(SENT_HASHES)
+            .map(|(tick, hash)| (*tick, *hash))
+            .collect();
+        self.send(&Message::Inputs {
+            first_tick,
+            directions,
+            received: self.remote_received,
+            hashes,
+        });
+    }
+
+    /// Assigns the oldest pending local direction to `tick`, unless it already has an input.
+    fn commit_local_input(&mut self, tick: u64) {
+        if !self.local_inputs.contains_key(&tick) && tick >= self.peer_received {
+            let direction = self.pending.pop();
+            self.local_inputs.insert(tick, direction);
+        }
+    }
+
+    /// Ticks before the input delay have no inputs at all, so they are always known.
+    const fn remote_known(&self, tick: u64) -> bool {
+        tick < self.remote_received
+    }
+
+    /// The remote direction for `tick`, predicting the snake keeps going if it is not known.
+    fn remote_direction(&self, tick: u64) -> Option<SnakeDirection> {
+        self.remote_inputs.get(&tick).copied().flatten()
+    }
+
+    /// Simulates the tick the game is due for, correcting mispredicted ticks first.
+    fn advance(&mut self, world: &mut World) {
+        let delta = world.resource::<Time>().delta();
+        let mut timer = world.query::<&mut MoveTimer>().single_mut(world);
+        if timer.0.tick(delta).just_finished() {
+            self.due = true;
+        }
+        if self.desync.is_some() {
+            return;
+        }
+
+        if let Some(tick) = self.first_misprediction() {
+            let current = world.resource::<Tick>().number;
+            self.snapshots[&tick].restore(world);
+            while world.resource::<Tick>().number < current && self.simulate(world) {}
+        }
+
+        let number = world.resource::<Tick>().number;
+        let confirmed = number.min(self.remote_received);
+        self.confirm(confirmed);
+        if !self.due {
+            return;
+        }
+        self.commit_local_input(number + self.input_delay);
+        if !self.remote_known(number) && number - confirmed >= self.max_prediction {
+            self.stalled += delta;
+            return;
+        }
+        self.due = false;
+        self.stalled = Duration::ZERO;
+        self.simulate(world);
+    }
+
+    /// Finds the first predicted tick whose remote input arrived and differs from the prediction.
+    fn first_misprediction(&mut self) -> Option<u64> {
+        while let Some((&tick, &predicted)) = self.predicted.first_key_value() {
+            if !self.remote_known(tick) {
+                return None;
+            }
+            self.predicted.pop_first();
+            if self.remote_direction(tick) != predicted {
+                return Some(tick);
+            }
+        }
+        None
+    }
+
+    /// Simulates the current tick, returning `false` if the game ended or has to wait.
+    fn simulate(&mut self, world: &mut World) -> bool {
+        let number = world.resource::<Tick>().number;
+        let known = self.remote_known(number);
+        if known {
+            self.predicted.remove(&number);
+        } else {
+            self.snapshots.insert(number, Snapshot::save(world));
+            self.predicted.insert(number, self.remote_direction(number));
+        }
+        let inputs = [
+            (
+                self.local_player,
+                self.local_inputs.get(&number).copied().flatten(),
+            ),
+            (self.remote_player, self.remote_direction(number)),
+        ]
+        .into_iter()
+        .filter_map(|(player, direction)| Some((player, direction?)))
+        .collect();
+        world.resource_mut::<TickInputs>().0.insert(number, inputs);
+        run_game_tick(world);
+        let hash = world.run_system_once_with(number, state_hash);
+        self.tick_hashes.insert(number, hash);
+        if world.resource::<NextState<GameState>>().0.is_none() {
+            return true;
+        }
+        if known {
+            self.predicted.clear();
+            self.snapshots.clear();
+        } else {
+            // Only a tick with known inputs may end the game, wait for them instead.
+            self.snapshots[&number].restore(world);
+            world.resource_mut::<NextState<GameState>>().0 = None;
+            self.due = true;
+        }
+        false
+    }
+
+    /// Forgets what is no longer needed to correct the ticks before `confirmed`.
+    fn confirm(&mut self, confirmed: u64) {
+        let unconfirmed = self.tick_hashes.split_off(&confirmed);
+        for (tick, hash) in std::mem::replace(&mut self.tick_hashes, unconfirmed) {
+            self.record_hash(tick, hash);
+        }
+        self.snapshots = self.snapshots.split_off(&confirmed);
+        self.remote_inputs = self.remote_inputs.split_off(&confirmed);
+        self.local_inputs = self
+            .local_inputs
+            .split_off(&confirmed.min(self.peer_received));
+    }
+
+    fn record_hash(&mut self, tick: u64, hash: u64) {
+        self.local_hashes.insert(tick, hash);
+        while self.local_hashes.len() > SENT_HASHES * 4 {
+            self.local_hashes.pop_first();
+        }
+        self.check_hash(tick);
+    }
+
+    fn check_hash(&mut self, tick: u64) {
+        let (Some(local), Some(remote)) =
+            (self.local_hashes.get(&tick), self.remote_hashes.get(&tick))
+        else {
+            return;
+        };
+        if local != remote && self.desync.is_none() {
+            error!("Desync at tick {tick}: local state {local:016x}, remote state {remote:016x}");
+            self.desync = Some(tick);
+        }
+        self.remote_hashes.remove(&tick);
+    }
+}
+
+#[derive(Component)]
+struct NetplayText;
+
+fn setup_netplay_hud(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..Default::default()
+        }),
+        NetplayText,
+    ));
+}
+
+fn update_netplay_hud(session: Res<Session>, mut text_query: Query<&mut Text, With<NetplayText>>) {
+    let message = if let Some(tick) = session.desync {
+        format!("Desync at tick {tick}")
+    } else if session.stalled > STALL_WARNING {
+        "Waiting for opponent...".to_owned()
+    } else {
+        String::new()
+    };
+    for mut text in &mut text_query {
+        if text.sections[0].value != message {
+            text.sections[0].value.clone_from(&message);
+        }
+    }
+}
+
+fn receive_packets(mut session: ResMut<Session>) {
+    session.receive();
+}
+
+fn send_packets(mut session: ResMut<Session>) {
+    session.send_inputs();
+}
+
+/// The local player always steers with the first control scheme.
+fn read_local_input(keyboard_input: Res<Input<KeyCode>>, mut session: ResMut<Session>) {
+    queue_pressed_directions(&keyboard_input, &CONTROL_SCHEMES[0], &mut session.pending);
+}
+
+fn advance_session(world: &mut World) {
+    world.resource_scope(|world, mut session: Mut<Session>| session.advance(world));
+}
+
+/// Hashes everything the game rules depend on, after `tick` was simulated.
+fn state_hash(
+    In(tick): In<u64>,
+    board: Board,
+    snake_query: Query<(&Snake, &Player, &Score, Has<Dead>)>,
+    fruit_query: Query<&FruitType>,
+    speed: Res<Speed>,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    tick.hash(&mut hasher);
+    sorted(
+        snake_query
+            .iter()
+            .map(|(snake, player, score, dead)| (player.0, snake.direction as u8, score.0, dead))
+            .collect(),
+    )
+    .hash(&mut hasher);
+    sorted(board.occupied_positions()).hash(&mut hasher);
+    sorted(fruit_query.iter().map(|fruit| *fruit as u8).collect()).hash(&mut hasher);
+    speed.interval.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Queries iterate in an order that depends on the history of the world, not on the game.
+fn sorted<T: Ord>(mut values: Vec<T>) -> Vec<T> {
+    values.sort_unstable();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        process::{Command, Stdio},
+        thread,
+    };
+
+    use bevy::time::TimeUpdateStrategy;
+    use clap::Parser;
+
+    use super::*;
+    use crate::{headless::MatchState, rules::RulesPlugin, TextureAtlasHandle};
+
+    /// Both peers of a match played over the loopback interface, started with `flags`.
+    fn peers(flags: &[&str]) -> Vec<App> {
+        let mut args = Args::parse_from(std::iter::once(&"snake-game").chain(flags));
+        let settings = MatchSettings {
+            seed: 3,
+            ..MatchSettings::from_args(&args)
+        };
+        settings.apply(&mut args);
+        let sockets = [(); 2].map(|()| UdpSocket::bind("127.0.0.1:0").unwrap());
+        let addresses = sockets
+            .each_ref()
+            .map(|socket| socket.local_addr().unwrap());
+        sockets
+            .into_iter()
+            .zip([
+                (addresses[1], Player(0), Player(1)),
+                (addresses[0], Player(1), Player(0)),
+            ])
+            .map(|(socket, (peer, local, remote))| {
+                let session = Session::new(socket, peer, settings, &args, (local, remote)).unwrap();
+                peer_app(&args, session)
+            })
+            .collect()
+    }
+
+    /// A game without a window playing an online match through `session`.
+    fn peer_app(args: &Args, session: Session) -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            RulesPlugin(args.match_config()),
+            NetplayPlugin,
+        ))
+        .init_resource::<Input<KeyCode>>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            30,
+        )))
+        .insert_resource(TextureAtlasHandle(Handle::default()))
+        .insert_resource(session);
+        app
+    }
+
+    /// Updates both peers `frames` times, calling `between` before every frame.
+    fn play(peers: &mut [App], frames: usize, mut between: impl FnMut(usize, &mut [App])) {
+        for frame in 0..frames {
+            between(frame, peers);
+            for app in &mut *peers {
+                app.update();
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn session(app: &App) -> &Session {
+        app.world.resource::<Session>()
+    }
+
+    #[test]
+    fn peers_agree_on_every_confirmed_tick() {
+        let mut peers = peers(&["--netcode", "lockstep"]);
+        play(&mut peers, 150, |_, _| {});
+        let [first, second] = [session(&peers[0]), session(&peers[1])];
+        assert_eq!((first.desync, second.desync), (None, None));
+        let shared: Vec<u64> = first
+            .local_hashes
+            .keys()
+            .filter(|tick| second.local_hashes.contains_key(tick))
+            .copied()
+            .collect();
+        assert!(!shared.is_empty(), "no tick was confirmed by both peers");
+        for tick in shared {
+            assert_eq!(first.local_hashes[&tick], second.local_hashes[&tick]);
+        }
+    }
+
+    #[test]
+    fn rollback_corrects_late_inputs() {
+        let mut peers = peers(&[
+            "--netcode",
+            "rollback",
+            "--input-delay",
+            "0",
+            "--simulate-latency",
+            "40",
+        ]);
+        play(&mut peers, 150, |frame, peers| {
+            let turns = [(10, 0, SnakeDirection::Up), (14, 1, SnakeDirection::Down)];
+            for (_, peer, direction) in turns.iter().filter(|(at, ..)| *at == frame) {
+                let mut session = peers[*peer].world.resource_mut::<Session>();
+                session.pending.push(*direction);
+            }
+        });
+        let snakes: Vec<String> = peers
+            .iter_mut()
+            .map(|app| {
+                assert_eq!(session(app).desync, None);
+                assert!(
+                    session(app).local_hashes.len() > 4,
+                    "the match barely moved"
+                );
+                format!("{:?}", MatchState::from_world(&mut app.world).snakes)
+            })
+            .collect();
+        let [first, second] = [&snakes[0], &snakes[1]];
+        assert_eq!(first, second);
+        assert!(first.contains("Up") && first.contains("Down"));
+    }
+
+    #[test]
+    fn a_changed_game_shows_up_as_a_desync() {
+        let mut peers = peers(&["--netcode", "lockstep"]);
+        play(&mut peers, 150, |frame, peers| {
+            if frame == 20 {
+                let world = &mut peers[1].world;
+                for mut score in world.query::<&mut Score>().iter_mut(world) {
+                    score.0 += 1;
+                }
+            }
+        });
+        assert!(session(&peers[0]).desync.is_some());
+        assert!(session(&peers[1]).desync.is_some());
+    }
+
+    /// Set to `host PORT` or `join PORT` when the test binary runs again as one of the peers of
+    /// `peers_in_separate_processes_end_the_same`.
+    const PEER_ROLE: &str = "SNAKE_NETPLAY_PEER";
+
+    /// Plays a match against a second game process over the loopback interface, from the
+    /// handshake to the end, and prints the hash of the final tick.
+    fn play_as_peer(role: &str) {
+        let (role, port) = role.split_once(' ').unwrap();
+        let port: u16 = port.parse().unwrap();
+        let mut args =
+            Args::parse_from(["snake-game", "--seed", "3", "--announce-to", "127.0.0.1:9"]);
+        let (joined, turn) = if role == "host" {
+            (Session::host(port, &mut args), (10, SnakeDirection::Up))
+        } else {
+            let host = SocketAddr::from(([127, 0, 0, 1], port));
+            (
+                Session::connect(host, &mut args),
+                (14, SnakeDirection::Down),
+            )
+        };
+        let mut app = peer_app(&args, joined.unwrap());
+        let mut end: Option<(usize, u64, u64)> = None;
+        // Both peers keep playing for a while after the end, so the slower one gets every input.
+        for frame in 0..3000 {
+            if frame == turn.0 {
+                app.world.resource_mut::<Session>().pending.push(turn.1);
+            }
+            app.update();
+            thread::sleep(Duration::from_millis(1));
+            let over = *app.world.resource::<State<GameState>>().get() == GameState::GameOver;
+            match end {
+                None if over => {
+                    let tick = app.world.resource::<Tick>().number - 1;
+                    let hash = app.world.run_system_once_with(tick, state_hash);
+                    end = Some((frame, tick, hash));
+                }
+                Some((ended, ..)) if frame > ended + 200 => break,
+                _ => {}
+            }
+        }
+        assert_eq!(session(&app).desync, None);
+        let (_, tick, hash) = end.expect("the match did not end");
+        println!("final tick {tick}: {hash:016x}");
+    }
+
+    #[test]
+    fn peers_in_separate_processes_end_the_same() {
+        if let Ok(role) = std::env::var(PEER_ROLE) {
+            play_as_peer(&role);
+            return;
+        }
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .and_then(|socket| socket.local_addr())
+            .unwrap()
+            .port();
+        let [mut host, client] = ["host", "join"].map(|role| {
+            Command::new(std::env::current_exe().unwrap())
+                .args([
+                    "--exact",
+                    "netplay::tests::peers_in_separate_processes_end_the_same",
+                    "--nocapture",
+                ])
+                .env(PEER_ROLE, format!("{role} {port}"))
+                .stdout(Stdio::piped())
+                .spawn()
+                .unwrap()
+        });
+        let client = client.wait_with_output().unwrap();
+        if !client.status.success() {
+            host.kill().unwrap();
+            panic!("the joining game failed");
+        }
+        let finals: Vec<String> = [host.wait_with_output().unwrap(), client]
+            .into_iter()
+            .map(|output| {
+                assert!(output.status.success());
+                // The test harness prints the name of the test on the same line.
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let (_, end) = stdout
+                    .split_once("final tick")
+                    .expect("a game did not print its final hash");
+                end.lines().next().unwrap_or_default().to_owned()
+            })
+            .collect();
+        assert_eq!(finals[0], finals[1]);
+    }
+}
@@ -0,0 +1,449 @@
+//! Finding online matches hosted on the local network.
+//!
+//! While waiting for an opponent, the host sends an [`Announcement`] every second, by default to
+//! the broadcast address. The lobby listens for them on [`DISCOVERY_PORT`] and lists every match
+//! announced recently, so joining one does not need its address. Announcing to `127.0.0.1`
+//! instead makes the match show up in a lobby running on the same machine.
+
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, UdpSocket},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+
+use clap::ValueEnum;
+
+use super::{variant_index, Reader, Session, MAX_PACKET_SIZE};
+use crate::{Args, BoardSize, GameMode, GameState};
+
+pub const DISCOVERY_PORT: u16 = 7776;
+const MAGIC: &[u8; 4] = b"SNKA";
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+/// Matches that were not announced for this long are dropped from the lobby.
+const ANNOUNCEMENT_LIFETIME: Duration = Duration::from_secs(3);
+const MAX_NAME_LENGTH: usize = 32;
+
+/// Shows the matches found on the local network and joins the one picked.
+pub struct LobbyPlugin(pub Args);
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Lobby {
+            args: self.0.clone(),
+            socket: None,
+            games: Vec::new(),
+            selected: 0,
+            status: None,
+            joining: None,
+        })
+        .add_systems(OnEnter(GameState::Lobby), setup_lobby)
+        .add_systems(
+            Update,
+            (
+                receive_announcements,
+                pick_game,
+                finish_joining,
+                update_lobby_text,
+            )
+                .chain()
+                .run_if(in_state(GameState::Lobby)),
+        )
+        .add_systems(OnExit(GameState::Lobby), clear_lobby);
+    }
+}
+
+/// What the host tells the local network about its match.
+#[derive(Clone, Debug)]
+pub struct Announcement {
+    pub name: String,
+    /// UDP port the host waits for an opponent on.
+    pub port: u16,
+    pub mode: GameMode,
+    /// Width and height of the area the snakes move in.
+    pub board: (u8, u8),
+    pub players: u8,
+    pub seats: u8,
+}
+
+impl Announcement {
+    /// Announces a match waiting for its second player.
+    pub fn new(name: &str, port: u16, mode: GameMode, board: BoardSize) -> Self {
+        let mut length = name.len().min(MAX_NAME_LENGTH);
+        while !name.is_char_boundary(length) {
+            length -= 1;
+        }
+        Self {
+            name: name[..length].to_owned(),
+            port,
+            mode,
+            board: (
+                u8::try_from(board.width).unwrap_or(u8::MAX),
+                u8::try_from(board.height).unwrap_or(u8::MAX),
+            ),
+            players: 1,
+            seats: 2,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(self.port.to_le_bytes());
+        bytes.push(variant_index(&self.mode));
+        bytes.extend([self.board.0, self.board.1, self.players, self.seats]);
+        bytes.extend(self.name.as_bytes());
+        bytes
+    }
+
+    /// Reads an announcement, returning `None` for anything that is not one.
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes.strip_prefix(MAGIC)?);
+        let port = u16::from_le_bytes([reader.u8()?, reader.u8()?]);
+        let mode = *GameMode::value_variants().get(usize::from(reader.u8()?))?;
+        let board = (reader.u8()?, reader.u8()?);
+        let players = reader.u8()?;
+        let seats = reader.u8()?;
+        Some(Self {
+            name: String::from_utf8_lossy(reader.0).into_owned(),
+            port,
+            mode,
+            board,
+            players,
+            seats,
+        })
+    }
+}
+
+/// Sends an [`Announcement`] over and over while the host waits.
+pub struct Announcer {
+    socket: UdpSocket,
+    target: SocketAddr,
+    packet: Vec<u8>,
+    last_sent: Option<Instant>,
+}
+
+impl Announcer {
+    pub fn new(target: SocketAddr, announcement: &Announcement) -> io::Result<Self> {
+        let socket = UdpSocket::bind(if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })?;
+        socket.set_broadcast(true)?;
+        Ok(Self {
+            socket,
+            target,
+            packet: announcement.encode(),
+            last_sent: None,
+        })
+    }
+
+    /// Sends the announcement again if the last one is older than `ANNOUNCE_INTERVAL`.
+    pub fn announce(&mut self) -> io::Result<()> {
+        if self
+            .last_sent
+            .is_some_and(|last_sent| last_sent.elapsed() < ANNOUNCE_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_sent = Some(Instant::now());
+        self.socket.send_to(&self.packet, self.target)?;
+        Ok(())
+    }
+}
+
+/// A match heard of on the local network.
+struct LanGame {
+    address: SocketAddr,
+    announcement: Announcement,
+    last_seen: Instant,
+}
+
+#[derive(Resource)]
+struct Lobby {
+    /// The settings used for the match joined, overridden by the ones of the host.
+    args: Args,
+    socket: Option<UdpSocket>,
+    games: Vec<LanGame>,
+    selected: usize,
+    /// Why the lobby cannot list or join matches.
+    status: Option<String>,
+    /// The match being joined, connecting on its own thread so the lobby keeps drawing.
+    joining: Option<Joining>,
+}
+
+struct Joining {
+    address: SocketAddr,
+    handshake: JoinHandle<(Args, io::Result<Session>)>,
+}
+
+#[derive(Component)]
+struct LobbyText;
+
+fn setup_lobby(mut commands: Commands, mut lobby: ResMut<Lobby>) {
+    match UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT))
+        .and_then(|socket| socket.set_nonblocking(true).map(|()| socket))
+    {
+        Ok(socket) => lobby.socket = Some(socket),
+        Err(error) => {
+            lobby.status = Some(format!(
+                "Could not listen on port {DISCOVERY_PORT}: {error}"
+            ));
+        }
+    }
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            left: Val::Px(8.0),
+            ..Default::default()
+        }),
+        LobbyText,
+    ));
+}
+
+/// Keeps the list of matches up to date with the announcements received.
+fn receive_announcements(mut lobby: ResMut<Lobby>) {
+    let lobby = &mut *lobby;
+    let Some(socket) = &lobby.socket else {
+        return;
+    };
+    let mut buffer = [0; MAX_PACKET_SIZE];
+    loop {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+            Err(error) => {
+                warn!("Could not receive announcements: {error}");
+                break;
+            }
+        };
+        let Some(announcement) = Announcement::decode(&buffer[..length]) else {
+            continue;
+        };
+        let address = SocketAddr::new(source.ip(), announcement.port);
+        let game = LanGame {
+            address,
+            announcement,
+            last_seen: Instant::now(),
+        };
+        match lobby.games.iter_mut().find(|game| game.address == address) {
+            Some(known) => *known = game,
+            None => lobby.games.push(game),
+        }
+    }
+    lobby
+        .games
+        .retain(|game| game.last_seen.elapsed() < ANNOUNCEMENT_LIFETIME);
+    lobby.selected = lobby.selected.min(lobby.games.len().saturating_sub(1));
+}
+
+/// Moves the selection with the arrow keys and starts joining the selected match on Enter.
+fn pick_game(keyboard_input: Res<Input<KeyCode>>, mut lobby: ResMut<Lobby>) {
+    if lobby.joining.is_some() {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        lobby.selected = lobby.selected.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) && lobby.selected + 1 < lobby.games.len() {
+        lobby.selected += 1;
+    }
+    if !keyboard_input.just_pressed(KeyCode::Return) {
+        return;
+    }
+    let Some(address) = lobby.games.get(lobby.selected).map(|game| game.address) else {
+        return;
+    };
+    let mut args = lobby.args.clone();
+    lobby.status = Some(format!("Joining {address}..."));
+    lobby.joining = Some(Joining {
+        address,
+        handshake: thread::spawn(move || {
+            let session = Session::connect(address, &mut args);
+            (args, session)
+        }),
+    });
+}
+
+/// Starts the match once the handshake started by `pick_game` is over, or tells why it failed.
+fn finish_joining(
+    mut commands: Commands,
+    mut lobby: ResMut<Lobby>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !lobby
+        .joining
+        .as_ref()
+        .is_some_and(|joining| joining.handshake.is_finished())
+    {
+        return;
+    }
+    let Some(Joining { address, handshake }) = lobby.joining.take() else {
+        return;
+    };
+    match handshake.join() {
+        Ok((args, Ok(session))) => {
+            let config = args.match_config();
+            commands.add(move |world: &mut World| config.insert_resources(world));
+            commands.insert_resource(session);
+            lobby.status = None;
+            next_state.set(GameState::Playing);
+        }
+        Ok((_, Err(error))) => lobby.status = Some(format!("Could not join {address}: {error}")),
+        Err(_) => lobby.status = Some(format!("Could not join {address}")),
+    }
+}
+
+fn update_lobby_text(lobby: Res<Lobby>, mut text_query: Query<&mut Text, With<LobbyText>>) {
+    let mut lines = vec!["Matches on the local network".to_owned(), String::new()];
+    if lobby.games.is_empty() {
+        lines.push("Looking for matches...".to_owned());
+    }
+    for (index, game) in lobby.games.iter().enumerate() {
+        let announcement = &game.announcement;
+        let mode = announcement
+            .mode
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default();
+        lines.push(format!(
+            "{} {}  {}  {}x{}  {}/{}",
+            if index == lobby.selected { ">" } else { " " },
+            announcement.name,
+            mode,
+            announcement.board.0,
+            announcement.board.1,
+            announcement.players,
+            announcement.seats,
+        ));
+    }
+    lines.push(String::new());
+    lines.push("Up/Down to pick a match, Enter to join".to_owned());
+    if let Some(status) = &lobby.status {
+        lines.push(status.clone());
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+fn clear_lobby(
+    mut commands: Commands,
+    mut lobby: ResMut<Lobby>,
+    text_query: Query<Entity, With<LobbyText>>,
+) {
+    lobby.socket = None;
+    lobby.joining = None;
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use bevy::ecs::system::RunSystemOnce;
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn names_are_cut_between_characters() {
+        let name = "é".repeat(MAX_NAME_LENGTH);
+        let announcement = Announcement::new(&name, 7777, GameMode::Classic, BoardSize::default());
+        assert_eq!(announcement.name, "é".repeat(MAX_NAME_LENGTH / 2));
+    }
+
+    #[test]
+    fn the_lobby_lists_matches_announced_over_loopback() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let target = socket.local_addr().unwrap();
+        let mut world = World::new();
+        world.insert_resource(Lobby {
+            args: Args::parse_from(["snake-game"]),
+            socket: Some(socket),
+            games: Vec::new(),
+            selected: 0,
+            status: None,
+            joining: None,
+        });
+
+        let board = BoardSize {
+            width: 30,
+            height: 20,
+        };
+        let mut announcer = Announcer::new(
+            target,
+            &Announcement::new("Den", 7777, GameMode::Venom, board),
+        )
+        .unwrap();
+        announcer.announce().unwrap();
+        let started = Instant::now();
+        while world.resource::<Lobby>().games.is_empty() && started.elapsed() < ANNOUNCE_INTERVAL {
+            thread::sleep(Duration::from_millis(10));
+            world.run_system_once(receive_announcements);
+        }
+
+        let lobby = world.resource::<Lobby>();
+        let [game] = &lobby.games[..] else {
+            panic!("the lobby lists {} matches", lobby.games.len());
+        };
+        assert_eq!(game.address, SocketAddr::from(([127, 0, 0, 1], 7777)));
+        let announcement = &game.announcement;
+        assert_eq!(announcement.name, "Den");
+        assert_eq!(announcement.mode, GameMode::Venom);
+        assert_eq!(announcement.board, (30, 20));
+        assert_eq!((announcement.players, announcement.seats), (1, 2));
+    }
+
+    #[test]
+    fn joining_leaves_the_lobby_running() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = silent.local_addr().unwrap();
+        let mut world = World::new();
+        world.insert_resource(Lobby {
+            args: Args::parse_from(["snake-game"]),
+            socket: None,
+            games: vec![LanGame {
+                address,
+                announcement: Announcement::new(
+                    "Den",
+                    address.port(),
+                    GameMode::Classic,
+                    BoardSize::default(),
+                ),
+                last_seen: Instant::now(),
+            }],
+            selected: 0,
+            status: None,
+            joining: None,
+        });
+        world.init_resource::<NextState<GameState>>();
+        let mut keyboard_input = Input::<KeyCode>::default();
+        keyboard_input.press(KeyCode::Return);
+        world.insert_resource(keyboard_input);
+
+        let started = Instant::now();
+        world.run_system_once(pick_game);
+        world.run_system_once(finish_joining);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        let lobby = world.resource::<Lobby>();
+        assert!(lobby.joining.is_some());
+        assert_eq!(lobby.status, Some(format!("Joining {address}...")));
+        assert!(world.resource::<NextState<GameState>>().0.is_none());
+    }
+}
@@ -0,0 +1,586 @@
+//! Collectible power-ups that give the snake timed effects.
+//!
+//! Every timed effect is a component that lives on the snake entity while it is active, and only
+//! changes that snake. Slow motion is the exception: every snake moves on the same `MoveTimer`, so
+//! it slows the whole board down, the snakes of the other players too. The HUD shows which player
+//! holds every effect.
+//!
+//! Adding a new effect only takes a component implementing [`Effect`], registering it with
+//! [`AppEffectExt::add_effect`] and a way to pick it up in [`PowerUpKind`].
+
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use rand::prelude::*;
+
+use crate::{
+    rules::{
+        move_snake, random_free_position, update_bonus_fruit, Board, GameRng, GameTick, Tick,
+        TickSet,
+    },
+    sprites::{Palette, SpriteFrames},
+    Apple, BonusFruit, Dead, GameState, Player, PlayerCount, Snake, Sprites, Tail, SPRITE_SIZE,
+};
+
+const POWER_UP_LIFETIME: f32 = 8.0;
+const EFFECT_DURATION: f32 = 6.0;
+pub const SLOW_MOTION_FACTOR: f32 = 2.0;
+const SHRINK_SEGMENTS: usize = 3;
+const MAGNET_RADIUS: i32 = 5;
+
+pub struct PowerUpPlugin;
+
+impl Plugin for PowerUpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (setup_power_up_timer, setup_effects_hud))
+            .add_systems(
+                GameTick,
+                (
+                    magnet_pull.in_set(TickSet::Move).after(move_snake),
+                    collect_power_up.in_set(TickSet::Eat),
+                    (spawn_power_up, update_power_up)
+                        .chain()
+                        .in_set(TickSet::Spawn)
+                        .after(update_bonus_fruit),
+                ),
+            )
+            .add_systems(OnExit(GameState::GameOver), clear_power_ups)
+            .add_effect::<Ghost>()
+            .add_effect::<SlowMotion>()
+            .add_effect::<Magnet>();
+    }
+}
+
+/// A timed effect applied to the snake, removed once its timer finishes.
+pub trait Effect: Component {
+    /// Name shown in the HUD while the effect is active.
+    const NAME: &'static str;
+
+    fn timer(&self) -> &Timer;
+
+    fn timer_mut(&mut self) -> &mut Timer;
+}
+
+pub trait AppEffectExt {
+    /// Registers the systems that count down an [`Effect`] and show it in the HUD.
+    fn add_effect<E: Effect>(&mut self) -> &mut Self;
+}
+
+impl AppEffectExt for App {
+    fn add_effect<E: Effect>(&mut self) -> &mut Self {
+        self.add_systems(GameTick, tick_effect::<E>.in_set(TickSet::Timers))
+            .add_systems(Update, update_effect_label::<E>)
+    }
+}
+
+macro_rules! effect {
+    ($(#[$meta:meta])* $effect:ident, $name:expr) => {
+        $(#[$meta])*
+        #[derive(Component, Clone)]
+        pub struct $effect(Timer);
+
+        impl Default for $effect {
+            fn default() -> Self {
+                Self(Timer::from_seconds(EFFECT_DURATION, TimerMode::Once))
+            }
+        }
+
+        impl Effect for $effect {
+            const NAME: &'static str = $name;
+
+            fn timer(&self) -> &Timer {
+                &self.0
+            }
+
+            fn timer_mut(&mut self) -> &mut Timer {
+                &mut self.0
+            }
+        }
+    };
+}
+
+effect!(
+    /// The snake can pass through its own tail.
+    Ghost,
+    "Ghost"
+);
+effect!(
+    /// The `MoveTimer` is lengthened by `SLOW_MOTION_FACTOR` while active, for every snake on the
+    /// board.
+    SlowMotion,
+    "Slow motion"
+);
+effect!(
+    /// Apples close to the head are pulled one tile towards it every move.
+    Magnet,
+    "Magnet"
+);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PowerUpKind {
+    Ghost,
+    SlowMotion,
+    Shrink,
+    Magnet,
+}
+
+impl PowerUpKind {
+    const ALL: [Self; 4] = [Self::Ghost, Self::SlowMotion, Self::Shrink, Self::Magnet];
+
+    fn sprite(self, frames: &SpriteFrames, palette: &Palette) -> TextureAtlasSprite {
+        let colors = &palette.power_ups;
+        let color = match self {
+            Self::Ghost => colors.ghost,
+            Self::SlowMotion => colors.slow_motion,
+            Self::Shrink => colors.shrink,
+            Self::Magnet => colors.magnet,
+        };
+        TextureAtlasSprite {
+            color,
+            ..TextureAtlasSprite::new(frames.power_up)
+        }
+    }
+}
+
+/// A power-up lying on the board waiting to be picked up.
+#[derive(Component, Clone)]
+pub struct PowerUp {
+    pub x: i32,
+    pub y: i32,
+    pub kind: PowerUpKind,
+    lifetime: Timer,
+}
+
+impl PowerUp {
+    pub(crate) fn new(x: i32, y: i32, kind: PowerUpKind) -> Self {
+        Self {
+            x,
+            y,
+            kind,
+            lifetime: Timer::from_seconds(POWER_UP_LIFETIME, TimerMode::Once),
+        }
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct PowerUpSpawnTimer(Timer);
+
+/// Container for the labels of the active effects.
+#[derive(Component)]
+struct EffectsHud;
+
+/// The label of an effect held by the snake of a player.
+#[derive(Component)]
+struct EffectLabel<E: Effect>(Player, PhantomData<E>);
+
+fn setup_power_up_timer(mut commands: Commands) {
+    commands.spawn(PowerUpSpawnTimer(Timer::from_seconds(
+        15.0,
+        TimerMode::Repeating,
+    )));
+}
+
+fn setup_effects_hud(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                right: Val::Px(8.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        EffectsHud,
+    ));
+}
+
+fn spawn_power_up(
+    mut commands: Commands,
+    mut power_up_timer_query: Query<&mut PowerUpSpawnTimer>,
+    board: Board,
+    sprites: Sprites,
+    mut rng: ResMut<GameRng>,
+    tick: Res<Tick>,
+) {
+    let mut timer = power_up_timer_query.single_mut();
+    if !timer.0.tick(tick.delta).just_finished() || !board.power_ups.is_empty() {
+        return;
+    }
+    let Some((x, y)) = random_free_position(*board.size, &board.occupied_positions(), &mut rng.0)
+    else {
+        return;
+    };
+    let kind = *PowerUpKind::ALL.choose(&mut rng.0).unwrap();
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: sprites.atlas.0.clone(),
+            transform: Transform::from_xyz((x as f32) * SPRITE_SIZE, (y as f32) * SPRITE_SIZE, 0.0),
+            sprite: kind.sprite(&sprites.frames, &sprites.palette),
+            ..Default::default()
+        },
+        PowerUp::new(x, y, kind),
+    ));
+}
+
+fn update_power_up(
+    mut commands: Commands,
+    mut power_up_query: Query<(&mut PowerUp, Entity)>,
+    tick: Res<Tick>,
+) {
+    for (mut power_up, entity) in &mut power_up_query {
+        if power_up.lifetime.tick(tick.delta).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn collect_power_up(
+    mut commands: Commands,
+    mut snake_query: Query<(&mut Snake, Entity), Without<Dead>>,
+    power_up_query: Query<(&PowerUp, Entity)>,
+) {
+    for (mut snake, snake_entity) in &mut snake_query {
+        let Some((power_up, entity)) = power_up_query
+            .iter()
+            .find(|(power_up, _)| snake.x == power_up.x && snake.y == power_up.y)
+        else {
+            continue;
+        };
+        commands.entity(entity).despawn();
+        match power_up.kind {
+            PowerUpKind::Ghost => {
+                commands.entity(snake_entity).insert(Ghost::default());
+            }
+            PowerUpKind::Magnet => {
+                commands.entity(snake_entity).insert(Magnet::default());
+            }
+            PowerUpKind::SlowMotion => {
+                commands.entity(snake_entity).insert(SlowMotion::default());
+            }
+            PowerUpKind::Shrink => {
+                let keep = snake.tail.len().saturating_sub(SHRINK_SEGMENTS).max(1);
+                for tail_entity in snake.tail.drain(keep..) {
+                    commands.entity(tail_entity).despawn();
+                }
+            }
+        }
+    }
+}
+
+/// Pulls apples within `MAGNET_RADIUS` of the head one tile closer every time the snake moves,
+/// never onto a tile something else is on.
+fn magnet_pull(
+    magnet_query: Query<&Snake, (With<Magnet>, Without<Dead>)>,
+    mut apple_query: Query<&mut Apple>,
+    snake_query: Query<&Snake, Without<Dead>>,
+    tail_query: Query<&Tail>,
+    bonus_query: Query<&BonusFruit>,
+    power_up_query: Query<&PowerUp>,
+) {
+    if magnet_query.is_empty() {
+        return;
+    }
+    // Like `Board::occupied_positions`, which cannot be used while the apples are moved.
+    let mut occupied: Vec<(i32, i32)> = apple_query
+        .iter()
+        .map(|apple| (apple.x, apple.y))
+        .chain(bonus_query.iter().map(|bonus| (bonus.x, bonus.y)))
+        .chain(
+            power_up_query
+                .iter()
+                .map(|power_up| (power_up.x, power_up.y)),
+        )
+        .chain(snake_query.iter().flat_map(|snake| {
+            std::iter::once((snake.x, snake.y)).chain(
+                tail_query
+                    .iter_many(&snake.tail)
+                    .map(|tail| (tail.x, tail.y)),
+            )
+        }))
+        .collect();
+    for snake in &magnet_query {
+        for mut apple in &mut apple_query {
+            let (dx, dy) = (snake.x - apple.x, snake.y - apple.y);
+            if dx.abs() + dy.abs() > MAGNET_RADIUS {
+                continue;
+            }
+            let step = if dx.abs() >= dy.abs() {
+                (apple.x + dx.signum(), apple.y)
+            } else {
+                (apple.x, apple.y + dy.signum())
+            };
+            if occupied.contains(&step) {
+                continue;
+            }
+            occupied.retain(|position| *position != (apple.x, apple.y));
+            occupied.push(step);
+            (apple.x, apple.y) = step;
+        }
+    }
+}
+
+fn tick_effect<E: Effect>(
+    mut commands: Commands,
+    mut effect_query: Query<(&mut E, Entity)>,
+    tick: Res<Tick>,
+) {
+    for (mut effect, entity) in &mut effect_query {
+        if effect.timer_mut().tick(tick.delta).finished() {
+            commands.entity(entity).remove::<E>();
+        }
+    }
+}
+
+/// Keeps a HUD label with the remaining time of an effect for every snake holding it, in the
+/// colour of its player.
+fn update_effect_label<E: Effect>(
+    mut commands: Commands,
+    effect_query: Query<(&E, &Player)>,
+    mut label_query: Query<(&mut Text, &EffectLabel<E>, Entity)>,
+    hud_query: Query<Entity, With<EffectsHud>>,
+    player_count: Res<PlayerCount>,
+    palette: Res<Palette>,
+) {
+    for (effect, player) in &effect_query {
+        let value = effect_label::<E>(*player, effect.timer().remaining_secs(), player_count.0);
+        if let Some((mut text, ..)) = label_query
+            .iter_mut()
+            .find(|(_, label, _)| label.0 == *player)
+        {
+            text.sections[0].value = value;
+            continue;
+        }
+        let label = commands
+            .spawn((
+                TextBundle::from_section(
+                    value,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: player.color(&palette),
+                        ..Default::default()
+                    },
+                ),
+                EffectLabel::<E>(*player, PhantomData),
+            ))
+            .id();
+        commands.entity(hud_query.single()).add_child(label);
+    }
+    for (_, label, entity) in &label_query {
+        if !effect_query.iter().any(|(_, player)| *player == label.0) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn effect_label<E: Effect>(player: Player, remaining: f32, player_count: u8) -> String {
+    if player_count == 1 {
+        format!("{} {remaining:.1}s", E::NAME)
+    } else {
+        format!("P{} {} {remaining:.1}s", player.0 + 1, E::NAME)
+    }
+}
+
+fn clear_power_ups(power_up_query: Query<Entity, With<PowerUp>>, mut commands: Commands) {
+    for power_up_entity in power_up_query.iter() {
+        commands.entity(power_up_entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        headless::{rules_app, MatchConfig},
+        rules::run_game_tick,
+        Dead, FruitType, MoveTimer, SnakeDirection, Speed,
+    };
+
+    /// A match without fruits, with `effect` on the snake.
+    fn app(effect: impl Bundle) -> App {
+        let mut app = rules_app(MatchConfig {
+            apples: Some(0),
+            ..MatchConfig::default()
+        });
+        let world = &mut app.world;
+        let snake = world.query_filtered::<Entity, With<Snake>>().single(world);
+        world.entity_mut(snake).insert(effect);
+        app
+    }
+
+    fn head(world: &mut World) -> (i32, i32) {
+        let snake = world.query::<&Snake>().single(world);
+        (snake.x, snake.y)
+    }
+
+    /// Where the apples are, sorted.
+    fn apples(world: &mut World) -> Vec<(i32, i32)> {
+        let mut apples: Vec<_> = world
+            .query::<&Apple>()
+            .iter(world)
+            .map(|apple| (apple.x, apple.y))
+            .collect();
+        apples.sort_unstable();
+        apples
+    }
+
+    #[test]
+    fn magnets_only_pull_apples_onto_free_tiles() {
+        let mut app = app(Magnet::default());
+        let world = &mut app.world;
+        // The snake heads right, so its head ends up at `x + 1` after the tick.
+        let (x, y) = head(world);
+        let free = (x + 1, y + 4);
+        let blocked = [(x + 2, y), (x + 3, y + 2), (x - 1, y + 2)];
+        for (x, y) in std::iter::once(free).chain(blocked) {
+            world.spawn((Apple { x, y }, FruitType::Apple));
+        }
+        // In the way of the last two: a bonus fruit and a power-up.
+        world.spawn(BonusFruit::new(x + 2, y + 2));
+        world.spawn(PowerUp {
+            x,
+            y: y + 2,
+            kind: PowerUpKind::Ghost,
+            lifetime: Timer::from_seconds(POWER_UP_LIFETIME, TimerMode::Once),
+        });
+        run_game_tick(world);
+
+        let mut expected = vec![(x + 1, y + 3)];
+        expected.extend(blocked);
+        expected.sort_unstable();
+        assert_eq!(apples(world), expected);
+    }
+
+    /// Leaves `E` on the snake for less than one more tick.
+    fn run_out<E: Effect>(world: &mut World) {
+        let mut effect = world.query::<&mut E>().single_mut(world);
+        let timer = effect.timer_mut();
+        timer.set_elapsed(timer.duration().saturating_sub(Duration::from_millis(1)));
+    }
+
+    fn has<E: Effect>(world: &mut World) -> bool {
+        world
+            .query_filtered::<(), (With<Snake>, With<E>)>()
+            .get_single(world)
+            .is_ok()
+    }
+
+    fn dead(world: &mut World) -> bool {
+        world
+            .query_filtered::<(), With<Dead>>()
+            .get_single(world)
+            .is_ok()
+    }
+
+    /// Turns the snake right back into its own tail on the next tick.
+    fn turn_back(world: &mut World) {
+        let mut snake = world.query::<&mut Snake>().single_mut(world);
+        snake.direction = match snake.direction {
+            SnakeDirection::Left => SnakeDirection::Right,
+            _ => SnakeDirection::Left,
+        };
+    }
+
+    #[test]
+    fn ghosts_pass_through_their_own_tail_until_it_wears_off() {
+        let mut app = app(Ghost::default());
+        let world = &mut app.world;
+        turn_back(world);
+        run_game_tick(world);
+        assert!(!dead(world));
+
+        run_out::<Ghost>(world);
+        turn_back(world);
+        run_game_tick(world);
+        assert!(!dead(world) && !has::<Ghost>(world));
+
+        turn_back(world);
+        run_game_tick(world);
+        assert!(dead(world));
+    }
+
+    #[test]
+    fn slow_motion_lengthens_the_moves_until_it_wears_off() {
+        let mut app = app(SlowMotion::default());
+        let world = &mut app.world;
+        let interval = |world: &mut World| {
+            let speed = world.resource::<Speed>().interval;
+            world
+                .query::<&MoveTimer>()
+                .single(world)
+                .0
+                .duration()
+                .as_secs_f32()
+                / speed
+        };
+        run_game_tick(world);
+        assert!((interval(world) - SLOW_MOTION_FACTOR).abs() < 1e-3);
+
+        run_out::<SlowMotion>(world);
+        run_game_tick(world);
+        run_game_tick(world);
+        assert!(!has::<SlowMotion>(world));
+        assert!((interval(world) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn shrinking_drops_tail_segments_but_not_the_last_one() {
+        let mut app = app(());
+        let world = &mut app.world;
+        let tail_length = |world: &mut World| world.query::<&Snake>().single(world).tail.len();
+        // Two more segments behind the three the snake starts with.
+        let (x, y) = head(world);
+        for x in [x - 4, x - 5] {
+            let segment = world.spawn(Tail { x, y }).id();
+            world
+                .query::<&mut Snake>()
+                .single_mut(world)
+                .tail
+                .push(segment);
+        }
+        assert_eq!(tail_length(world), SHRINK_SEGMENTS + 2);
+        for remaining in [2, 1, 1] {
+            let (x, y) = head(world);
+            world.spawn(PowerUp {
+                x: x + 1,
+                y,
+                kind: PowerUpKind::Shrink,
+                lifetime: Timer::from_seconds(POWER_UP_LIFETIME, TimerMode::Once),
+            });
+            run_game_tick(world);
+            assert_eq!(tail_length(world), remaining);
+            assert!(!dead(world));
+        }
+    }
+
+    #[test]
+    fn magnets_pull_apples_closer_until_they_wear_off() {
+        let mut app = app(Magnet::default());
+        let world = &mut app.world;
+        let (x, y) = head(world);
+        world.spawn((Apple { x: x + 1, y: y + 4 }, FruitType::Apple));
+        run_game_tick(world);
+        assert_eq!(apples(world), [(x + 1, y + 3)]);
+
+        run_out::<Magnet>(world);
+        run_game_tick(world);
+        assert!(!has::<Magnet>(world));
+        let pulled = apples(world);
+        run_game_tick(world);
+        assert_eq!(apples(world), pulled);
+    }
+
+    #[test]
+    fn effect_labels_name_the_player_when_there_are_several() {
+        assert_eq!(effect_label::<Ghost>(Player(0), 2.5, 1), "Ghost 2.5s");
+        assert_eq!(
+            effect_label::<SlowMotion>(Player(1), 4.0, 2),
+            "P2 Slow motion 4.0s"
+        );
+    }
+}
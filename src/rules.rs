@@ -0,0 +1,944 @@
+//! The rules of the game, played one `GameTick` at a time.
+//!
+//! Every system that changes the state of a match runs in the [`GameTick`] schedule, in the order
+//! of the [`TickSet`]s, so the same seed and inputs always play out the same. That is what lets
+//! headless matches, replays and both peers of an online match agree.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+
+use bevy::{
+    ecs::{schedule::ScheduleLabel, system::SystemParam},
+    prelude::*,
+};
+
+use rand::prelude::*;
+
+use crate::{
+    accelerate_over_time,
+    ai::{prepare_bots, Bot, BotPlugin},
+    clear_game_scene,
+    headless::MatchConfig,
+    power_ups::{Ghost, PowerUp, PowerUpPlugin},
+    reset_board_filled, reset_speed, setup_glass, setup_snake, setup_timers, setup_wall,
+    sprites::{Palette, SpriteFrames},
+    update_move_timer, Apple, AppleCount, BoardFilled, BoardSize, BonusFruit, BonusSpawnTimer,
+    Bots, Dead, FruitType, GameMode, GameState, KeyboardDirection, MoveTimer, Player, PlayerCount,
+    Score, Snake, SnakeDirection, Speed, Sprites, Tail, TextureAtlasHandle, Wall, SPRITE_SIZE,
+};
+
+/// The game rules, everything needed to simulate a match without a window.
+pub struct RulesPlugin(pub MatchConfig);
+
+impl Plugin for RulesPlugin {
+    fn build(&self, app: &mut App) {
+        self.0.insert_resources(&mut app.world);
+        app.init_resource::<Tick>()
+            .init_resource::<SpriteFrames>()
+            .init_resource::<Palette>()
+            .init_resource::<PendingTicks>()
+            .init_resource::<TickInputs>()
+            .init_resource::<BoardFilled>()
+            .add_state::<GameState>()
+            .add_plugins((PowerUpPlugin, BotPlugin))
+            .add_systems(Startup, setup_timers)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (
+                    (
+                        setup_snake,
+                        setup_glass,
+                        setup_wall,
+                        // The first tick of a new match already runs at the starting speed.
+                        (reset_speed, update_move_timer).chain(),
+                        reset_board_filled,
+                    ),
+                    apply_deferred,
+                    spawn_apples,
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                GameTick,
+                (
+                    TickSet::Input,
+                    TickSet::Move,
+                    TickSet::Collide,
+                    TickSet::Eat,
+                    TickSet::Spawn,
+                    TickSet::Timers,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                GameTick,
+                (
+                    apply_tick_inputs.in_set(TickSet::Input),
+                    move_snake.in_set(TickSet::Move),
+                    (tail_collision, wall_collision, head_collision, fill_board)
+                        .in_set(TickSet::Collide),
+                    ((eat_apple, empty_tail).chain(), eat_bonus_fruit).in_set(TickSet::Eat),
+                    apply_deferred.after(TickSet::Eat).before(TickSet::Spawn),
+                    end_match.in_set(TickSet::Spawn).before(spawn_apples),
+                    // Spawning draws from the `GameRng`, so it has to happen in the same order every
+                    // time for a seed to play out the same.
+                    (spawn_apples, spawn_bonus_fruit, update_bonus_fruit)
+                        .chain()
+                        .in_set(TickSet::Spawn),
+                    (accelerate_over_time, update_move_timer)
+                        .chain()
+                        .in_set(TickSet::Timers),
+                ),
+            )
+            .add_systems(OnExit(GameState::GameOver), clear_game_scene);
+    }
+}
+
+impl MatchConfig {
+    /// Inserts the resources the rules read the settings of the match from.
+    pub fn insert_resources(&self, world: &mut World) {
+        world.insert_resource(AppleCount(
+            self.apples.unwrap_or_else(|| self.mode.apple_count()),
+        ));
+        world.insert_resource(self.mode);
+        world.insert_resource(self.board);
+        world.insert_resource(Speed::new(self.difficulty.speed_curve()));
+        world.insert_resource(PlayerCount(
+            self.players + u8::try_from(self.bots.len()).unwrap_or(u8::MAX),
+        ));
+        world.insert_resource(Bots {
+            kinds: self.bots.clone(),
+            timeout: self.bot_timeout,
+        });
+        world.insert_resource(GameRng(StdRng::seed_from_u64(self.seed)));
+    }
+}
+
+/// Advances the game by one move of the snakes.
+///
+/// Everything that changes the state of the game runs here, using the `Tick` instead of the frame
+/// time, so the game only depends on the inputs of each tick. That lets both peers of an online
+/// match simulate the exact same game.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameTick;
+
+/// The steps of a `GameTick`, in the order they run.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TickSet {
+    Input,
+    Move,
+    Collide,
+    Eat,
+    Spawn,
+    Timers,
+}
+
+/// The tick being simulated and how much game time it covers.
+#[derive(Resource, Clone, Default)]
+pub struct Tick {
+    pub number: u64,
+    pub delta: Duration,
+}
+
+/// How many ticks should be simulated this frame.
+#[derive(Resource, Default)]
+pub struct PendingTicks(u32);
+
+/// Directions chosen by each player for upcoming ticks, applied right before the tick is simulated.
+#[derive(Resource, Default)]
+pub struct TickInputs(pub BTreeMap<u64, Vec<(Player, SnakeDirection)>>);
+
+/// The only source of randomness for the game rules, so a seed always plays out the same.
+#[derive(Resource, Clone)]
+pub struct GameRng(pub StdRng);
+
+/// Runs the `GameTick` schedule once for every pending tick, stopping early if a tick ended the game.
+pub fn run_game_ticks(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<PendingTicks>().0);
+    for _ in 0..pending {
+        run_game_tick(world);
+        if world.resource::<NextState<GameState>>().0.is_some() {
+            break;
+        }
+    }
+}
+
+/// Simulates the current tick, covering one interval of the `MoveTimer`.
+pub fn run_game_tick(world: &mut World) {
+    let delta = world.query::<&MoveTimer>().single(world).0.duration();
+    world.resource_mut::<Tick>().delta = delta;
+    world.run_schedule(GameTick);
+    world.resource_mut::<Tick>().number += 1;
+    prepare_bots(world);
+}
+
+/// Paces the game locally, one tick every time the `MoveTimer` finishes.
+pub fn advance_move_timer(
+    mut move_timer_query: Query<&mut MoveTimer>,
+    mut pending_ticks: ResMut<PendingTicks>,
+    time: Res<Time>,
+) {
+    let mut timer = move_timer_query.single_mut();
+    if timer.0.tick(time.delta()).just_finished() {
+        pending_ticks.0 += 1;
+    }
+}
+
+pub fn apply_tick_inputs(
+    tick: Res<Tick>,
+    mut tick_inputs: ResMut<TickInputs>,
+    mut snake_query: Query<(&mut KeyboardDirection, &Player)>,
+) {
+    let Some(inputs) = tick_inputs.0.remove(&tick.number) else {
+        return;
+    };
+    for (player, direction) in inputs {
+        for (mut keyboard_direction, _) in snake_query.iter_mut().filter(|(_, p)| **p == player) {
+            keyboard_direction.0.push(direction);
+        }
+    }
+}
+
+/// Everything that takes up a tile inside the walls.
+#[derive(SystemParam)]
+pub struct Board<'w, 's> {
+    pub apples: Query<'w, 's, &'static Apple>,
+    pub bonus_fruits: Query<'w, 's, &'static BonusFruit>,
+    pub power_ups: Query<'w, 's, &'static PowerUp>,
+    pub snakes: Query<'w, 's, &'static Snake, Without<Dead>>,
+    pub tails: Query<'w, 's, &'static Tail>,
+    pub size: Res<'w, BoardSize>,
+}
+
+impl Board<'_, '_> {
+    /// Positions of every apple, bonus fruit, power-up, snake head and tail segment.
+    pub fn occupied_positions(&self) -> Vec<(i32, i32)> {
+        self.apples
+            .iter()
+            .map(|apple| (apple.x, apple.y))
+            .chain(self.bonus_fruits.iter().map(|bonus| (bonus.x, bonus.y)))
+            .chain(
+                self.power_ups
+                    .iter()
+                    .map(|power_up| (power_up.x, power_up.y)),
+            )
+            .chain(self.snake_tiles())
+            .collect()
+    }
+
+    /// Tiles covered by the snakes still alive, heads and tails.
+    pub fn snake_tiles(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.snakes.iter().flat_map(|snake| {
+            std::iter::once((snake.x, snake.y)).chain(
+                self.tails
+                    .iter_many(&snake.tail)
+                    .map(|tail| (tail.x, tail.y)),
+            )
+        })
+    }
+}
+
+/// Picks a random tile inside the walls that is not in `occupied`.
+/// Returns `None` when there is no free tile left.
+pub fn random_free_position(
+    board_size: BoardSize,
+    occupied: &[(i32, i32)],
+    rng: &mut impl Rng,
+) -> Option<(i32, i32)> {
+    board_size
+        .positions()
+        .filter(|position| !occupied.contains(position))
+        .choose(rng)
+}
+
+/// Keeps the board filled with `AppleCount` apples, spawning the missing ones on free tiles.
+fn spawn_apples(
+    mut commands: Commands,
+    apple_count: Res<AppleCount>,
+    game_mode: Res<GameMode>,
+    board: Board,
+    sprites: Sprites,
+    mut rng: ResMut<GameRng>,
+) {
+    let missing = apple_count.0.saturating_sub(board.apples.iter().count());
+    if missing == 0 {
+        return;
+    }
+    let mut occupied = board.occupied_positions();
+    for _ in 0..missing {
+        let Some((x, y)) = random_free_position(*board.size, &occupied, &mut rng.0) else {
+            break;
+        };
+        occupied.push((x, y));
+        let fruit = game_mode
+            .fruit_weights()
+            .choose_weighted(&mut rng.0, |(_, weight)| *weight)
+            .map_or(FruitType::Apple, |(fruit, _)| *fruit);
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: sprites.atlas.0.clone(),
+                transform: Transform::from_xyz(
+                    (x as f32) * SPRITE_SIZE,
+                    (y as f32) * SPRITE_SIZE,
+                    0.0,
+                ),
+                sprite: fruit.sprite(&sprites.frames, &sprites.palette),
+                ..Default::default()
+            },
+            Apple { x, y },
+            fruit,
+        ));
+    }
+}
+
+/// Picks a random free tile right next to one of the apples.
+/// Returns `None` when every tile around them is taken.
+fn free_position_next_to_apple(board: &Board, rng: &mut impl Rng) -> Option<(i32, i32)> {
+    let occupied = board.occupied_positions();
+    let mut neighbours: Vec<(i32, i32)> = board
+        .apples
+        .iter()
+        .flat_map(|apple| {
+            [(0, 1), (0, -1), (1, 0), (-1, 0)].map(|(dx, dy)| (apple.x + dx, apple.y + dy))
+        })
+        .filter(|position| board.size.contains(*position) && !occupied.contains(position))
+        .collect();
+    // Sorted, so the pick does not depend on the order the apples were spawned in.
+    neighbours.sort_unstable();
+    neighbours.dedup();
+    neighbours.choose(rng).copied()
+}
+
+/// Every time the `BonusSpawnTimer` finishes there is a chance of a bonus fruit showing up next to
+/// an apple, unless there is one on the board already.
+fn spawn_bonus_fruit(
+    mut commands: Commands,
+    mut bonus_spawn_timer_query: Query<&mut BonusSpawnTimer>,
+    board: Board,
+    sprites: Sprites,
+    mut rng: ResMut<GameRng>,
+    tick: Res<Tick>,
+) {
+    let mut timer = bonus_spawn_timer_query.single_mut();
+    if !timer.0.tick(tick.delta).just_finished()
+        || !board.bonus_fruits.is_empty()
+        || !rng.0.gen_bool(0.5)
+    {
+        return;
+    }
+    let Some((x, y)) = free_position_next_to_apple(&board, &mut rng.0) else {
+        return;
+    };
+    commands.spawn((
+        SpriteSheetBundle {
+            texture_atlas: sprites.atlas.0.clone(),
+            transform: Transform::from_xyz((x as f32) * SPRITE_SIZE, (y as f32) * SPRITE_SIZE, 0.0),
+            sprite: TextureAtlasSprite {
+                color: sprites.palette.fruits.bonus,
+                ..TextureAtlasSprite::new(sprites.frames.fruits.bonus)
+            },
+            ..Default::default()
+        },
+        BonusFruit::new(x, y),
+    ));
+}
+
+/// Counts down the lifetime of bonus fruits, removing the ones that were not eaten in time.
+pub fn update_bonus_fruit(
+    mut commands: Commands,
+    mut bonus_query: Query<(&mut BonusFruit, Entity)>,
+    tick: Res<Tick>,
+) {
+    for (mut bonus, entity) in &mut bonus_query {
+        if bonus.lifetime.tick(tick.delta).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn move_snake(
+    mut snake_query: Query<(&mut Snake, &mut KeyboardDirection), Without<Dead>>,
+    mut tail_query: Query<&mut Tail>,
+) {
+    for (mut snake, mut keyboard_direction) in &mut snake_query {
+        if let Some(direction) = keyboard_direction.0.pop() {
+            if !(snake.direction == SnakeDirection::Up && direction == SnakeDirection::Down
+                || snake.direction == SnakeDirection::Down && direction == SnakeDirection::Up
+                || snake.direction == SnakeDirection::Left && direction == SnakeDirection::Right
+                || snake.direction == SnakeDirection::Right && direction == SnakeDirection::Left)
+            {
+                snake.direction = direction;
+            }
+        }
+
+        let mut prev_snake_x = snake.x;
+        let mut prev_snake_y = snake.y;
+        match snake.direction {
+            SnakeDirection::Up => snake.y += 1,
+            SnakeDirection::Down => snake.y -= 1,
+            SnakeDirection::Left => snake.x -= 1,
+            SnakeDirection::Right => snake.x += 1,
+        }
+        for entity in &snake.tail {
+            let mut tail = tail_query.get_mut(*entity).unwrap();
+            let prev_tail_x = tail.x;
+            let prev_tail_y = tail.y;
+            tail.x = prev_snake_x;
+            tail.y = prev_snake_y;
+            prev_snake_x = prev_tail_x;
+            prev_snake_y = prev_tail_y;
+        }
+    }
+}
+
+fn eat_apple(
+    mut commands: Commands,
+    mut snake_query: Query<(&mut Snake, &mut Score, &Player), Without<Dead>>,
+    apple_query: Query<(&Apple, &FruitType, Entity)>,
+    tail_query: Query<&Tail>,
+    texture_atlas_handle: Res<TextureAtlasHandle>,
+    palette: Res<Palette>,
+    mut speed: ResMut<Speed>,
+) {
+    let mut eaten = Vec::new();
+    for (mut snake, mut score, player) in &mut snake_query {
+        let Some((_, fruit, entity)) = apple_query.iter().find(|(apple, _, entity)| {
+            snake.x == apple.x && snake.y == apple.y && !eaten.contains(entity)
+        }) else {
+            continue;
+        };
+        eaten.push(entity);
+        commands.entity(entity).despawn();
+        score.0 += fruit.score();
+        let mut tail = snake.tail.clone();
+        let texture_atlas = &texture_atlas_handle.0;
+        let last_tail = tail_query.get(*tail.last().unwrap()).unwrap();
+        if fruit.growth() < 0 {
+            let lost = fruit.growth().unsigned_abs().min(tail.len());
+            for tail_entity in tail.drain(tail.len() - lost..) {
+                commands.entity(tail_entity).despawn();
+            }
+        }
+        for _ in 0..fruit.growth().max(0).unsigned_abs() {
+            let z = -(tail.len() as f32);
+            tail.push(
+                commands
+                    .spawn((
+                        Tail {
+                            x: last_tail.x,
+                            y: last_tail.y,
+                        },
+                        SpriteSheetBundle {
+                            texture_atlas: texture_atlas.clone(),
+                            transform: Transform::from_translation(Vec3 { x: 0.0, y: 0.0, z }),
+                            sprite: TextureAtlasSprite {
+                                color: player.color(&palette),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                    ))
+                    .id(),
+            );
+        }
+        snake.tail = tail;
+        speed.step(fruit.speed_steps());
+    }
+}
+
+/// A snake that lost its whole tail to poison is dead.
+fn empty_tail(mut commands: Commands, snake_query: Query<(&Snake, Entity), Without<Dead>>) {
+    for (_, entity) in snake_query
+        .iter()
+        .filter(|(snake, _)| snake.tail.is_empty())
+    {
+        commands.entity(entity).insert(Dead);
+    }
+}
+
+fn eat_bonus_fruit(
+    mut commands: Commands,
+    mut snake_query: Query<(&Snake, &mut Score), Without<Dead>>,
+    bonus_query: Query<(&BonusFruit, Entity)>,
+) {
+    for (snake, mut score) in &mut snake_query {
+        if let Some((bonus, entity)) = bonus_query
+            .iter()
+            .find(|(bonus, _)| snake.x == bonus.x && snake.y == bonus.y)
+        {
+            commands.entity(entity).despawn();
+            score.0 += bonus.score();
+        }
+    }
+}
+
+/// Running into the tail of any snake still alive is deadly, a ghost only passes through its own.
+fn tail_collision(
+    mut commands: Commands,
+    snake_query: Query<(&Snake, Entity, Has<Ghost>), Without<Dead>>,
+    tail_query: Query<&Tail>,
+) {
+    for (snake, snake_entity, ghost) in &snake_query {
+        let crashed = snake_query.iter().any(|(other, other_entity, _)| {
+            !(ghost && other_entity == snake_entity)
+                && tail_query
+                    .iter_many(&other.tail)
+                    .any(|tail| tail.x == snake.x && tail.y == snake.y)
+        });
+        if crashed {
+            commands.entity(snake_entity).insert(Dead);
+        }
+    }
+}
+
+fn wall_collision(
+    mut commands: Commands,
+    snake_query: Query<(&Snake, Entity), Without<Dead>>,
+    wall_query: Query<&Wall>,
+) {
+    for (snake, snake_entity) in &snake_query {
+        if wall_query.iter().any(|w| w.x == snake.x && w.y == snake.y) {
+            commands.entity(snake_entity).insert(Dead);
+        }
+    }
+}
+
+/// Snakes that run into each other head first both die.
+fn head_collision(mut commands: Commands, snake_query: Query<(&Snake, Entity), Without<Dead>>) {
+    for [(snake, snake_entity), (other, other_entity)] in snake_query.iter_combinations() {
+        if snake.x == other.x && snake.y == other.y {
+            commands.entity(snake_entity).insert(Dead);
+            commands.entity(other_entity).insert(Dead);
+        }
+    }
+}
+
+/// Ends the match once at most one of several snakes is left alive, the only snake of the match
+/// died, or every snake steered by a player died while bots play on.
+fn end_match(
+    snake_query: Query<(Has<Dead>, Has<Bot>), With<Snake>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let snakes = snake_query.iter().count();
+    let alive = snake_query.iter().filter(|(dead, _)| !dead).count();
+    let players = snake_query.iter().filter(|(_, bot)| !bot).count();
+    let players_alive = snake_query
+        .iter()
+        .filter(|(dead, bot)| !dead && !bot)
+        .count();
+    if (snakes > 0 && alive == 0)
+        || (snakes > 1 && alive == 1)
+        || (players > 0 && players_alive == 0)
+    {
+        game_state.set(GameState::GameOver);
+    }
+}
+
+/// The game is won once the snakes cover every tile of the board.
+fn fill_board(
+    board: Board,
+    mut board_filled: ResMut<BoardFilled>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let covered: HashSet<(i32, i32)> = board
+        .snake_tiles()
+        .filter(|position| board.size.contains(*position))
+        .collect();
+    if covered.len() == board.size.tile_count() {
+        board_filled.0 = true;
+        game_state.set(GameState::GameOver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        ai::{BotKind, BotStrategy},
+        headless::{rules_app, Match, MatchState},
+        BONUS_FRUIT_LIFETIME, BONUS_FRUIT_MAX_SCORE,
+    };
+
+    /// The tile the snake moves to on the next tick.
+    fn next_tile(world: &mut World) -> (i32, i32) {
+        let snake = world.query::<&Snake>().single(world);
+        match snake.direction {
+            SnakeDirection::Up => (snake.x, snake.y + 1),
+            SnakeDirection::Down => (snake.x, snake.y - 1),
+            SnakeDirection::Left => (snake.x - 1, snake.y),
+            SnakeDirection::Right => (snake.x + 1, snake.y),
+        }
+    }
+
+    /// Puts a `fruit` on the tile the snake moves to next, then plays that tick.
+    fn eat(app: &mut App, fruit: FruitType) {
+        let world = &mut app.world;
+        let next = next_tile(world);
+        let mut apple_query = world.query::<(&mut Apple, &mut FruitType)>();
+        let (mut apple, mut kind) = apple_query.iter_mut(world).next().unwrap();
+        (apple.x, apple.y) = next;
+        *kind = fruit;
+        run_game_tick(world);
+        app.update();
+    }
+
+    /// The score of the snake and the length of its tail.
+    fn snake(app: &mut App) -> (u32, usize) {
+        let world = &mut app.world;
+        let (snake, score) = world.query::<(&Snake, &Score)>().single(world);
+        (score.0, snake.tail.len())
+    }
+
+    #[test]
+    fn fruits_change_the_score_length_and_speed() {
+        for (fruit, points, growth, speed) in [
+            (FruitType::Apple, 1, 1, Ordering::Less),
+            (FruitType::GoldenApple, 5, 2, Ordering::Less),
+            (FruitType::Berry, 1, 1, Ordering::Greater),
+        ] {
+            let mut app = rules_app(MatchConfig::default());
+            // Halfway along the curve, so berries have room to slow the snake down.
+            app.world.resource_mut::<Speed>().interval = 0.2;
+            let (score, length) = snake(&mut app);
+            eat(&mut app, fruit);
+            assert_eq!(
+                snake(&mut app),
+                (score + points, length + growth),
+                "{fruit:?}"
+            );
+            let interval = app.world.resource::<Speed>().interval;
+            assert_eq!(interval.total_cmp(&0.2), speed, "{fruit:?}");
+        }
+    }
+
+    #[test]
+    fn poison_shrinks_the_snake_until_it_dies() {
+        let mut app = rules_app(MatchConfig::default());
+        let (score, length) = snake(&mut app);
+        eat(&mut app, FruitType::Poison);
+        assert_eq!(snake(&mut app), (score + 3, length - 2));
+        while snake(&mut app).1 > 0 {
+            eat(&mut app, FruitType::Poison);
+        }
+        let state = MatchState::from_world(&mut app.world);
+        assert!(state.over && !state.snakes[0].alive);
+    }
+
+    #[test]
+    fn every_mode_keeps_its_fruits_on_free_tiles() {
+        for (mode, count) in [
+            (GameMode::Classic, 1),
+            (GameMode::Orchard, 3),
+            (GameMode::Venom, 4),
+        ] {
+            let mut game = Match::new(MatchConfig {
+                mode,
+                ..MatchConfig::default()
+            });
+            let state = game.state();
+            let fruits: HashSet<(i32, i32)> =
+                state.fruits.iter().map(|(_, position)| *position).collect();
+            assert_eq!(fruits.len(), count, "{mode:?}");
+            assert!(
+                fruits
+                    .iter()
+                    .all(|fruit| state.board.contains(*fruit)
+                        && !state.snakes[0].body.contains(fruit))
+            );
+        }
+    }
+
+    #[test]
+    fn the_same_seed_plays_out_the_same() {
+        let play = || {
+            let mut game = Match::new(MatchConfig {
+                mode: GameMode::Venom,
+                players: 0,
+                bots: vec![
+                    BotKind::BuiltIn(BotStrategy::Pathfinder),
+                    BotKind::BuiltIn(BotStrategy::Cautious),
+                ],
+                seed: 7,
+                ..MatchConfig::default()
+            });
+            let mut states = vec![format!("{:?}", game.state())];
+            while game.step() && states.len() < 300 {
+                states.push(format!("{:?}", game.state()));
+            }
+            states
+        };
+        let first = play();
+        assert!(first.len() > 20, "the bots did not get to play");
+        assert_eq!(first, play());
+    }
+
+    /// Adds segments on `tiles` to the end of the tail of the only snake.
+    fn extend_tail(world: &mut World, tiles: impl IntoIterator<Item = (i32, i32)>) {
+        let segments: Vec<Entity> = tiles
+            .into_iter()
+            .map(|(x, y)| world.spawn(Tail { x, y }).id())
+            .collect();
+        world
+            .query::<&mut Snake>()
+            .single_mut(world)
+            .tail
+            .extend(segments);
+    }
+
+    #[test]
+    fn filling_the_board_wins_on_any_size() {
+        for (width, height) in [(8, 8), (9, 9), (8, 11), (13, 9)] {
+            let board = BoardSize { width, height };
+            let mut app = rules_app(MatchConfig {
+                board,
+                ..MatchConfig::default()
+            });
+            let head = {
+                let world = &mut app.world;
+                let snake = world.query::<&Snake>().single(world);
+                (snake.x, snake.y)
+            };
+            extend_tail(
+                &mut app.world,
+                board.positions().filter(|position| *position != head),
+            );
+            app.world.run_system_once(fill_board);
+            app.update();
+
+            let state = MatchState::from_world(&mut app.world);
+            assert!(state.over && state.filled, "{board} was not won");
+            assert_eq!(state.winner(), Some(0));
+        }
+    }
+
+    /// Plays a two player match on `board`, steering the snakes once at the start.
+    fn two_players(board: BoardSize, turns: [SnakeDirection; 2]) -> MatchState {
+        let mut game = Match::new(MatchConfig {
+            players: 2,
+            board,
+            apples: Some(0),
+            ..MatchConfig::default()
+        });
+        for (player, direction) in turns.into_iter().enumerate() {
+            game.steer(player, direction);
+        }
+        while game.step() {}
+        game.state()
+    }
+
+    #[test]
+    fn snakes_running_into_each_other_head_first_both_die() {
+        // The heads start six rows apart in the same column.
+        let state = two_players(
+            BoardSize::default(),
+            [SnakeDirection::Up, SnakeDirection::Down],
+        );
+        assert_eq!(state.tick, 3);
+        assert_eq!(state.snakes[0].body[0], state.snakes[1].body[0]);
+        assert!(state.snakes.iter().all(|snake| !snake.alive));
+        assert_eq!(state.winner(), None);
+    }
+
+    #[test]
+    fn a_crash_leaves_the_other_snakes_playing() {
+        let mut game = Match::new(MatchConfig {
+            players: 3,
+            apples: Some(0),
+            ..MatchConfig::default()
+        });
+        // The first snake heads for the bottom wall, the others along their rows.
+        game.steer(0, SnakeDirection::Down);
+        while game.state().snakes[0].alive {
+            assert!(game.step());
+        }
+        let crashed = game.state();
+        assert!(!crashed.over);
+        assert!(crashed.snakes[1].alive && crashed.snakes[2].alive);
+        assert_eq!(crashed.winner(), None);
+
+        // The crashed snake no longer moves, the others do until only one of them is left.
+        assert!(game.step());
+        let state = game.state();
+        assert_eq!(state.snakes[0].body, crashed.snakes[0].body);
+        assert_ne!(state.snakes[1].body[0], crashed.snakes[1].body[0]);
+        while game.step() {}
+        let state = game.state();
+        assert_eq!(
+            state
+                .snakes
+                .iter()
+                .map(|snake| snake.alive)
+                .collect::<Vec<_>>(),
+            [false, true, false]
+        );
+        assert_eq!(state.winner(), Some(1));
+    }
+
+    #[test]
+    fn running_into_another_snake_kills_only_the_one_running_into_it() {
+        // The first snake turns into the end of the second one, which keeps going.
+        let board = BoardSize {
+            width: 8,
+            height: 8,
+        };
+        let state = two_players(board, [SnakeDirection::Up, SnakeDirection::Left]);
+        assert!(state.snakes[1].body[1..].contains(&state.snakes[0].body[0]));
+        assert!(!state.snakes[0].alive && state.snakes[1].alive);
+        assert_eq!(state.winner(), Some(1));
+    }
+
+    #[test]
+    fn only_the_snake_eating_a_fruit_scores() {
+        let mut app = rules_app(MatchConfig {
+            players: 2,
+            ..MatchConfig::default()
+        });
+        let world = &mut app.world;
+        let (x, y) = world
+            .query::<(&Snake, &Player)>()
+            .iter(world)
+            .find(|(_, player)| player.0 == 1)
+            .map(|(snake, _)| (snake.x - 1, snake.y))
+            .unwrap();
+        let mut apple = world.query::<&mut Apple>().single_mut(world);
+        (apple.x, apple.y) = (x, y);
+        run_game_tick(world);
+
+        let state = MatchState::from_world(world);
+        let scores: Vec<u32> = state.snakes.iter().map(|snake| snake.score).collect();
+        assert_eq!(scores, [0, FruitType::Apple.score()]);
+        assert_eq!(state.snakes[1].body.len(), 5);
+    }
+
+    /// Finishes the `BonusSpawnTimer` of a new match played with `seed`, returning where the bonus
+    /// fruit showed up, if it did. `setup` gets to change the board first.
+    fn spawn_bonus(seed: u64, setup: impl FnOnce(&mut World)) -> Option<(i32, i32)> {
+        let mut app = rules_app(MatchConfig {
+            seed,
+            ..MatchConfig::default()
+        });
+        let world = &mut app.world;
+        setup(world);
+        let mut timer = world.query::<&mut BonusSpawnTimer>().single_mut(world);
+        let almost_finished = timer.0.duration().saturating_sub(Duration::from_millis(1));
+        timer.0.set_elapsed(almost_finished);
+        world.resource_mut::<Tick>().delta = Duration::from_millis(1);
+        world.run_system_once(spawn_bonus_fruit);
+        let bonus = world.query::<&BonusFruit>().get_single(world).ok()?;
+        Some((bonus.x, bonus.y))
+    }
+
+    #[test]
+    fn bonus_fruits_show_up_next_to_an_apple() {
+        let mut spawned = 0;
+        for seed in 0..20 {
+            let mut apple = (0, 0);
+            let Some((x, y)) = spawn_bonus(seed, |world| {
+                let apple_entity = world.query::<&Apple>().single(world);
+                apple = (apple_entity.x, apple_entity.y);
+            }) else {
+                continue;
+            };
+            spawned += 1;
+            assert_eq!((x - apple.0).abs() + (y - apple.1).abs(), 1, "seed {seed}");
+        }
+        assert!(spawned > 0, "no bonus fruit showed up");
+
+        // An apple in the corner with both of its neighbours taken leaves no room.
+        for seed in 0..20 {
+            let bonus = spawn_bonus(seed, |world| {
+                let mut apple = world.query::<&mut Apple>().single_mut(world);
+                (apple.x, apple.y) = (2, 2);
+                extend_tail(world, [(3, 2), (2, 3)]);
+            });
+            assert_eq!(bonus, None, "seed {seed}");
+        }
+    }
+
+    /// Turns that keep the snake going around a small square, so it lasts as long as needed.
+    const CIRCLE: [SnakeDirection; 8] = [
+        SnakeDirection::Right,
+        SnakeDirection::Right,
+        SnakeDirection::Up,
+        SnakeDirection::Up,
+        SnakeDirection::Left,
+        SnakeDirection::Left,
+        SnakeDirection::Down,
+        SnakeDirection::Down,
+    ];
+
+    /// Heads the snake where it has to go on this tick to keep going around in circles.
+    fn turn(world: &mut World) {
+        let number = world.resource::<Tick>().number;
+        let turn = CIRCLE[usize::try_from(number).unwrap() % CIRCLE.len()];
+        world.query::<&mut Snake>().single_mut(world).direction = turn;
+    }
+
+    /// Plays `ticks` ticks with the snake going around in circles.
+    fn circle(app: &mut App, ticks: usize) {
+        for _ in 0..ticks {
+            turn(&mut app.world);
+            run_game_tick(&mut app.world);
+            app.update();
+        }
+    }
+
+    /// A match without apples, with a bonus fruit in the corner away from the snake.
+    fn bonus_app() -> App {
+        let mut app = rules_app(MatchConfig {
+            apples: Some(0),
+            ..MatchConfig::default()
+        });
+        app.world
+            .spawn((BonusFruit::new(2, 2), Visibility::Inherited));
+        app
+    }
+
+    /// The points the snake gets for a bonus fruit that lay on the board for `ticks` ticks.
+    fn bonus_points_after(ticks: usize) -> u32 {
+        let mut app = bonus_app();
+        circle(&mut app, ticks);
+        let world = &mut app.world;
+        turn(world);
+        let next = next_tile(world);
+        let mut bonus = world.query::<&mut BonusFruit>().single_mut(world);
+        (bonus.x, bonus.y) = next;
+        let (score, _) = snake(&mut app);
+        circle(&mut app, 1);
+        assert!(MatchState::from_world(&mut app.world)
+            .bonus_fruits
+            .is_empty());
+        snake(&mut app).0 - score
+    }
+
+    #[test]
+    fn bonus_fruits_are_worth_more_the_sooner_they_are_eaten() {
+        let early = bonus_points_after(0);
+        let late = bonus_points_after(15);
+        assert_eq!(early, BONUS_FRUIT_MAX_SCORE);
+        assert!(0 < late && late < early, "{late} points when eaten late");
+    }
+
+    #[test]
+    fn bonus_fruits_disappear_when_not_eaten_in_time() {
+        let mut app = bonus_app();
+        let mut elapsed = Duration::ZERO;
+        while !MatchState::from_world(&mut app.world)
+            .bonus_fruits
+            .is_empty()
+        {
+            circle(&mut app, 1);
+            elapsed += app.world.resource::<Tick>().delta;
+            assert!(!MatchState::from_world(&mut app.world).over);
+        }
+        let lifetime = Duration::from_secs_f32(BONUS_FRUIT_LIFETIME);
+        assert!(elapsed >= lifetime, "gone after {elapsed:?}");
+        assert!(elapsed < lifetime + app.world.resource::<Tick>().delta);
+    }
+}
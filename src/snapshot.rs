@@ -0,0 +1,297 @@
+//! Snapshots of everything the game rules change during a tick.
+//!
+//! Restoring a snapshot puts the board back exactly how it was before the tick, so the ticks
+//! after it can be simulated again with different inputs. Entities spawned by the rules (tails,
+//! fruits and power-ups) are despawned and spawned again, while snakes keep their entity.
+
+use std::time::Duration;
+
+use bevy::{ecs::world::EntityWorldMut, prelude::*};
+
+use crate::{
+    power_ups::{Ghost, Magnet, PowerUp, PowerUpSpawnTimer, SlowMotion},
+    rules::{GameRng, Tick},
+    Apple, BoardFilled, BonusFruit, BonusSpawnTimer, Dead, FruitType, KeyboardDirection, MoveTimer,
+    Score, Snake, SnakeDirection, Speed, Tail, TextureAtlasHandle,
+};
+
+/// The state of the game right before a tick.
+pub struct Snapshot {
+    tick: Tick,
+    speed: Speed,
+    rng: GameRng,
+    board_filled: BoardFilled,
+    move_interval: Duration,
+    bonus_spawn_timer: BonusSpawnTimer,
+    power_up_spawn_timer: PowerUpSpawnTimer,
+    snakes: Vec<SnakeSnapshot>,
+    apples: Vec<(Apple, FruitType, TextureAtlasSprite, Transform)>,
+    bonus_fruits: Vec<(BonusFruit, TextureAtlasSprite, Transform)>,
+    power_ups: Vec<(PowerUp, TextureAtlasSprite, Transform)>,
+}
+
+struct SnakeSnapshot {
+    entity: Entity,
+    x: i32,
+    y: i32,
+    direction: SnakeDirection,
+    tail: Vec<(Tail, TextureAtlasSprite, Transform)>,
+    score: u32,
+    dead: bool,
+    keyboard_direction: KeyboardDirection,
+    ghost: Option<Ghost>,
+    slow_motion: Option<SlowMotion>,
+    magnet: Option<Magnet>,
+}
+
+impl Snapshot {
+    pub fn save(world: &mut World) -> Self {
+        let mut tail_query = world.query::<(&Tail, &TextureAtlasSprite, &Transform)>();
+        let snakes = world
+            .query::<(
+                Entity,
+                &Snake,
+                &Score,
+                Has<Dead>,
+                &KeyboardDirection,
+                Option<&Ghost>,
+                Option<&SlowMotion>,
+                Option<&Magnet>,
+            )>()
+            .iter(world)
+            .map(
+                |(entity, snake, score, dead, keyboard_direction, ghost, slow_motion, magnet)| {
+                    SnakeSnapshot {
+                        entity,
+                        x: snake.x,
+                        y: snake.y,
+                        direction: snake.direction,
+                        tail: tail_query
+                            .iter_many(world, &snake.tail)
+                            .map(|(tail, sprite, transform)| {
+                                (tail.clone(), sprite.clone(), *transform)
+                            })
+                            .collect(),
+                        score: score.0,
+                        dead,
+                        keyboard_direction: keyboard_direction.clone(),
+                        ghost: ghost.cloned(),
+                        slow_motion: slow_motion.cloned(),
+                        magnet: magnet.cloned(),
+                    }
+                },
+            )
+            .collect();
+        let apples = world
+            .query::<(&Apple, &FruitType, &TextureAtlasSprite, &Transform)>()
+            .iter(world)
+            .map(|(apple, fruit, sprite, transform)| {
+                (apple.clone(), *fruit, sprite.clone(), *transform)
+            })
+            .collect();
+        Self {
+            tick: world.resource::<Tick>().clone(),
+            speed: world.resource::<Speed>().clone(),
+            rng: world.resource::<GameRng>().clone(),
+            board_filled: world.resource::<BoardFilled>().clone(),
+            move_interval: world.query::<&MoveTimer>().single(world).0.duration(),
+            bonus_spawn_timer: world.query::<&BonusSpawnTimer>().single(world).clone(),
+            power_up_spawn_timer: world.query::<&PowerUpSpawnTimer>().single(world).clone(),
+            snakes,
+            apples,
+            bonus_fruits: save_sprites(world),
+            power_ups: save_sprites(world),
+        }
+    }
+
+    pub fn restore(&self, world: &mut World) {
+        world.insert_resource(self.tick.clone());
+        world.insert_resource(self.speed.clone());
+        world.insert_resource(self.rng.clone());
+        world.insert_resource(self.board_filled.clone());
+        world
+            .query::<&mut MoveTimer>()
+            .single_mut(world)
+            .0
+            .set_duration(self.move_interval);
+        *world.query::<&mut BonusSpawnTimer>().single_mut(world) = self.bonus_spawn_timer.clone();
+        *world.query::<&mut PowerUpSpawnTimer>().single_mut(world) =
+            self.power_up_spawn_timer.clone();
+
+        let texture_atlas = world.resource::<TextureAtlasHandle>().0.clone();
+        despawn_all::<Apple>(world);
+        for (apple, fruit, sprite, transform) in &self.apples {
+            world.spawn((
+                sprite_bundle(&texture_atlas, sprite, transform),
+                apple.clone(),
+                *fruit,
+            ));
+        }
+        restore_sprites(world, &texture_atlas, &self.bonus_fruits);
+        restore_sprites(world, &texture_atlas, &self.power_ups);
+
+        despawn_all::<Tail>(world);
+        for saved in &self.snakes {
+            let tail = saved
+                .tail
+                .iter()
+                .map(|(tail, sprite, transform)| {
+                    world
+                        .spawn((
+                            sprite_bundle(&texture_atlas, sprite, transform),
+                            tail.clone(),
+                        ))
+                        .id()
+                })
+                .collect();
+            let mut entity = world.entity_mut(saved.entity);
+            entity.insert((
+                Snake {
+                    x: saved.x,
+                    y: saved.y,
+                    direction: saved.direction,
+                    tail,
+                },
+                Score(saved.score),
+                saved.keyboard_direction.clone(),
+            ));
+            restore_component(&mut entity, saved.dead.then_some(Dead));
+            restore_component(&mut entity, saved.ghost.clone());
+            restore_component(&mut entity, saved.slow_motion.clone());
+            restore_component(&mut entity, saved.magnet.clone());
+        }
+    }
+}
+
+fn save_sprites<C: Component + Clone>(
+    world: &mut World,
+) -> Vec<(C, TextureAtlasSprite, Transform)> {
+    world
+        .query::<(&C, &TextureAtlasSprite, &Transform)>()
+        .iter(world)
+        .map(|(component, sprite, transform)| (component.clone(), sprite.clone(), *transform))
+        .collect()
+}
+
+fn restore_sprites<C: Component + Clone>(
+    world: &mut World,
+    texture_atlas: &Handle<TextureAtlas>,
+    saved: &[(C, TextureAtlasSprite, Transform)],
+) {
+    despawn_all::<C>(world);
+    for (component, sprite, transform) in saved {
+        world.spawn((
+            sprite_bundle(texture_atlas, sprite, transform),
+            component.clone(),
+        ));
+    }
+}
+
+fn sprite_bundle(
+    texture_atlas: &Handle<TextureAtlas>,
+    sprite: &TextureAtlasSprite,
+    transform: &Transform,
+) -> SpriteSheetBundle {
+    SpriteSheetBundle {
+        texture_atlas: texture_atlas.clone(),
+        sprite: sprite.clone(),
+        transform: *transform,
+        ..Default::default()
+    }
+}
+
+fn despawn_all<C: Component>(world: &mut World) {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<C>>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+}
+
+fn restore_component<C: Component>(entity: &mut EntityWorldMut, component: Option<C>) {
+    if let Some(component) = component {
+        entity.insert(component);
+    } else {
+        entity.remove::<C>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        headless::{rules_app, MatchConfig, MatchState},
+        rules::{run_game_tick, TickInputs},
+        GameMode, Player,
+    };
+
+    /// Plays `ticks` ticks, turning the snake up on the first one.
+    fn play(world: &mut World, ticks: usize) -> String {
+        let number = world.resource::<Tick>().number;
+        world
+            .resource_mut::<TickInputs>()
+            .0
+            .insert(number, vec![(Player(0), SnakeDirection::Up)]);
+        for _ in 0..ticks {
+            run_game_tick(world);
+        }
+        format!("{:?}", MatchState::from_world(world))
+    }
+
+    #[test]
+    fn restoring_a_snapshot_plays_out_the_same_again() {
+        let mut app = rules_app(MatchConfig {
+            mode: GameMode::Venom,
+            ..MatchConfig::default()
+        });
+        let world = &mut app.world;
+        play(world, 2);
+        let before = format!("{:?}", MatchState::from_world(world));
+        let snapshot = Snapshot::save(world);
+
+        let played = play(world, 5);
+        snapshot.restore(world);
+        assert_eq!(format!("{:?}", MatchState::from_world(world)), before);
+        assert_eq!(play(world, 5), played);
+    }
+
+    #[test]
+    fn restoring_a_snapshot_undoes_filling_the_board() {
+        let mut app = rules_app(MatchConfig::default());
+        let world = &mut app.world;
+        // The tail of the snake covers every tile but the one it moves to next, so the next tick
+        // fills the board. Its last segment is doubled, like after eating, so it does not leave a
+        // tile behind.
+        let state = MatchState::from_world(world);
+        let body = &state.snakes[0].body;
+        let next = (body[0].0 + 1, body[0].1);
+        let mut tiles: Vec<(i32, i32)> = state
+            .board
+            .positions()
+            .filter(|tile| *tile != next && !body.contains(tile))
+            .collect();
+        tiles.extend(tiles.last().copied());
+        let segments: Vec<Entity> = tiles
+            .into_iter()
+            .map(|(x, y)| {
+                world
+                    .spawn((Tail { x, y }, SpriteSheetBundle::default()))
+                    .id()
+            })
+            .collect();
+        world
+            .query::<&mut Snake>()
+            .single_mut(world)
+            .tail
+            .extend(segments);
+        let before = format!("{:?}", MatchState::from_world(world));
+        let snapshot = Snapshot::save(world);
+        run_game_tick(world);
+        assert!(MatchState::from_world(world).filled);
+
+        snapshot.restore(world);
+        assert_eq!(format!("{:?}", MatchState::from_world(world)), before);
+    }
+}
@@ -0,0 +1,381 @@
+//! Sound effects for what happens on the board and in the lobby.
+//!
+//! The sounds are built into the game like the sprite sheet. The rules know nothing about them,
+//! the systems here notice what changed on the board and play the sound for it. Bevy never starts
+//! a sound without an audio device, so once a sound has waited too long to start the game stays
+//! silent instead of piling up sounds that will never play, until the sound settings change, like
+//! when M is pressed.
+//!
+//! The music is not built in, it is synthesized while it plays, see [`music`].
+
+pub mod music;
+
+use std::collections::HashMap;
+
+use bevy::{audio::Volume, ecs::system::SystemParam, prelude::*};
+
+use music::MusicPlugin;
+
+use crate::{ai::Bot, power_ups::PowerUp, Dead, GameState, Score, Snake, SnakeDirection};
+
+/// Seconds a sound may wait to start before the audio output counts as missing.
+const START_TIMEOUT: f32 = 1.0;
+
+/// How loud the game is.
+#[derive(clap::Args, Resource, Clone)]
+pub struct SoundSettings {
+    /// Volume of everything, in percent
+    #[arg(long = "volume", value_name = "PERCENT", default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub master: u8,
+    /// Volume of the sound effects, in percent of the volume of everything
+    #[arg(long = "sfx-volume", value_name = "PERCENT", default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub sfx: u8,
+    /// Volume of the music, in percent of the volume of everything
+    #[arg(long = "music-volume", value_name = "PERCENT", default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub music: u8,
+    /// Start without sound, M turns it on and off at any time
+    #[arg(long)]
+    pub mute: bool,
+}
+
+impl SoundSettings {
+    /// The volume something set to `percent` of the volume of everything plays at, from 0 to 1.
+    fn volume(&self, percent: u8) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            f32::from(self.master) * f32::from(percent) / 10_000.0
+        }
+    }
+}
+
+pub struct SoundPlugin(pub SoundSettings);
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.0.clone())
+            .init_resource::<NoAudioDevice>()
+            .add_plugins(MusicPlugin)
+            .add_systems(Startup, load_sounds)
+            .add_systems(
+                Update,
+                (
+                    toggle_mute,
+                    retry_sounds.run_if(resource_changed::<SoundSettings>()),
+                    drop_unplayed_sounds,
+                    turn_sounds,
+                    eat_sounds,
+                    power_up_sounds,
+                    death_sounds,
+                    menu_sounds.run_if(in_state(GameState::Lobby)),
+                ),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct Sounds {
+    turn: Handle<AudioSource>,
+    eat: Handle<AudioSource>,
+    power_up: Handle<AudioSource>,
+    death: Handle<AudioSource>,
+    menu: Handle<AudioSource>,
+}
+
+/// Set once a sound never started, which happens when there is no audio device, and cleared when
+/// the sound settings change.
+#[derive(Resource, Default)]
+struct NoAudioDevice(bool);
+
+/// Marks a sound effect until it starts playing, Bevy despawns it once it is over.
+#[derive(Component)]
+struct SoundEffect(Timer);
+
+/// Plays sound effects at the volume of the settings.
+#[derive(SystemParam)]
+struct Sfx<'w, 's> {
+    commands: Commands<'w, 's>,
+    sounds: Res<'w, Sounds>,
+    settings: Res<'w, SoundSettings>,
+    no_audio_device: Res<'w, NoAudioDevice>,
+}
+
+impl Sfx<'_, '_> {
+    fn play(&mut self, sound: fn(&Sounds) -> &Handle<AudioSource>) {
+        let volume = self.settings.volume(self.settings.sfx);
+        if volume <= 0.0 || self.no_audio_device.0 {
+            return;
+        }
+        self.commands.spawn((
+            AudioBundle {
+                source: sound(&self.sounds).clone(),
+                settings: PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(volume)),
+            },
+            SoundEffect(Timer::from_seconds(START_TIMEOUT, TimerMode::Once)),
+        ));
+    }
+}
+
+fn load_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Sounds {
+        turn: asset_server.load("embedded://sounds/turn.wav"),
+        eat: asset_server.load("embedded://sounds/eat.wav"),
+        power_up: asset_server.load("embedded://sounds/power_up.wav"),
+        death: asset_server.load("embedded://sounds/death.wav"),
+        menu: asset_server.load("embedded://sounds/menu.wav"),
+    });
+}
+
+fn toggle_mute(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<SoundSettings>) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        settings.mute = !settings.mute;
+    }
+}
+
+/// Tries playing sounds again, the sound that never started might have been held up by something
+/// else, like a slow frame.
+fn retry_sounds(mut no_audio_device: ResMut<NoAudioDevice>) {
+    no_audio_device.0 = false;
+}
+
+fn drop_unplayed_sounds(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sound_query: Query<(Entity, &mut SoundEffect), Without<AudioSink>>,
+    mut no_audio_device: ResMut<NoAudioDevice>,
+) {
+    for (entity, mut sound) in &mut sound_query {
+        if sound.0.tick(time.delta()).just_finished() {
+            commands.entity(entity).despawn();
+            if !no_audio_device.0 {
+                warn!("A sound did not start playing, the game stays silent until M is pressed");
+                no_audio_device.0 = true;
+            }
+        }
+    }
+}
+
+/// Plays a blip when a snake steered from the keyboard turns.
+fn turn_sounds(
+    mut sfx: Sfx,
+    snake_query: Query<(Entity, &Snake, Has<Bot>), Changed<Snake>>,
+    mut directions: Local<HashMap<Entity, SnakeDirection>>,
+    mut removed: RemovedComponents<Snake>,
+) {
+    for entity in removed.read() {
+        directions.remove(&entity);
+    }
+    let mut turned = false;
+    for (entity, snake, bot) in &snake_query {
+        if let Some(direction) = directions.insert(entity, snake.direction) {
+            turned |= !bot && direction != snake.direction;
+        }
+    }
+    if turned {
+        sfx.play(|sounds| &sounds.turn);
+    }
+}
+
+fn eat_sounds(
+    mut sfx: Sfx,
+    score_query: Query<(Entity, &Score), Changed<Score>>,
+    mut scores: Local<HashMap<Entity, u32>>,
+    mut removed: RemovedComponents<Score>,
+) {
+    for entity in removed.read() {
+        scores.remove(&entity);
+    }
+    let mut ate = false;
+    for (entity, score) in &score_query {
+        if let Some(previous) = scores.insert(entity, score.0) {
+            ate |= score.0 > previous;
+        }
+    }
+    if ate {
+        sfx.play(|sounds| &sounds.eat);
+    }
+}
+
+/// Plays a jingle when a power-up disappears under the head of a snake, instead of running out.
+fn power_up_sounds(
+    mut sfx: Sfx,
+    power_up_query: Query<(Entity, &PowerUp)>,
+    snake_query: Query<&Snake>,
+    mut power_ups: Local<HashMap<Entity, (i32, i32)>>,
+) {
+    let collected = power_ups.iter().any(|(entity, position)| {
+        !power_up_query.contains(*entity)
+            && snake_query
+                .iter()
+                .any(|snake| (snake.x, snake.y) == *position)
+    });
+    *power_ups = power_up_query
+        .iter()
+        .map(|(entity, power_up)| (entity, (power_up.x, power_up.y)))
+        .collect();
+    if collected {
+        sfx.play(|sounds| &sounds.power_up);
+    }
+}
+
+fn death_sounds(mut sfx: Sfx, dead_query: Query<(), Added<Dead>>) {
+    if !dead_query.is_empty() {
+        sfx.play(|sounds| &sounds.death);
+    }
+}
+
+fn menu_sounds(mut sfx: Sfx, keyboard_input: Res<Input<KeyCode>>) {
+    if keyboard_input.any_just_pressed([KeyCode::Up, KeyCode::Down, KeyCode::Return]) {
+        sfx.play(|sounds| &sounds.menu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::power_ups::PowerUpKind;
+
+    /// The sound effects of a match, with every sound told apart by its handle.
+    fn world(settings: SoundSettings) -> (World, Schedule) {
+        let mut world = World::new();
+        world.insert_resource(Sounds {
+            turn: Handle::weak_from_u128(1),
+            eat: Handle::weak_from_u128(2),
+            power_up: Handle::weak_from_u128(3),
+            death: Handle::weak_from_u128(4),
+            menu: Handle::weak_from_u128(5),
+        });
+        world.insert_resource(settings);
+        world.init_resource::<NoAudioDevice>();
+        world.init_resource::<Input<KeyCode>>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((
+            turn_sounds,
+            eat_sounds,
+            power_up_sounds,
+            death_sounds,
+            menu_sounds,
+        ));
+        (world, schedule)
+    }
+
+    fn settings() -> SoundSettings {
+        SoundSettings {
+            master: 100,
+            sfx: 100,
+            music: 50,
+            mute: false,
+        }
+    }
+
+    /// Runs the sound systems once and returns the sounds they started, which are then stopped.
+    fn played(world: &mut World, schedule: &mut Schedule) -> Vec<Handle<AudioSource>> {
+        schedule.run(world);
+        world.clear_trackers();
+        let mut sound_query =
+            world.query_filtered::<(Entity, &Handle<AudioSource>), With<SoundEffect>>();
+        let sounds: Vec<_> = sound_query
+            .iter(world)
+            .map(|(entity, source)| (entity, source.clone()))
+            .collect();
+        sounds
+            .into_iter()
+            .map(|(entity, source)| {
+                world.despawn(entity);
+                source
+            })
+            .collect()
+    }
+
+    /// A snake steered from the keyboard at `(x, y)`.
+    fn snake(world: &mut World, x: i32, y: i32) -> Entity {
+        world
+            .spawn((
+                Snake {
+                    x,
+                    y,
+                    direction: SnakeDirection::Right,
+                    tail: Vec::new(),
+                },
+                Score(0),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn every_event_plays_its_sound() {
+        let (mut world, mut schedule) = world(settings());
+        let snake = snake(&mut world, 5, 5);
+        let power_up = world.spawn(PowerUp::new(6, 5, PowerUpKind::Magnet)).id();
+        assert!(played(&mut world, &mut schedule).is_empty());
+
+        world.get_mut::<Snake>(snake).unwrap().direction = SnakeDirection::Up;
+        assert_eq!(
+            played(&mut world, &mut schedule),
+            [Handle::weak_from_u128(1)]
+        );
+
+        world.get_mut::<Score>(snake).unwrap().0 += 1;
+        assert_eq!(
+            played(&mut world, &mut schedule),
+            [Handle::weak_from_u128(2)]
+        );
+
+        world.despawn(power_up);
+        world.get_mut::<Snake>(snake).unwrap().x = 6;
+        assert_eq!(
+            played(&mut world, &mut schedule),
+            [Handle::weak_from_u128(3)]
+        );
+
+        world.entity_mut(snake).insert(Dead);
+        assert_eq!(
+            played(&mut world, &mut schedule),
+            [Handle::weak_from_u128(4)]
+        );
+
+        world
+            .resource_mut::<Input<KeyCode>>()
+            .press(KeyCode::Return);
+        assert_eq!(
+            played(&mut world, &mut schedule),
+            [Handle::weak_from_u128(5)]
+        );
+    }
+
+    #[test]
+    fn nothing_plays_when_muted_or_without_audio() {
+        let quiet = [
+            SoundSettings {
+                mute: true,
+                ..settings()
+            },
+            SoundSettings {
+                master: 0,
+                ..settings()
+            },
+            SoundSettings {
+                sfx: 0,
+                ..settings()
+            },
+        ];
+        for (settings, no_audio_device) in quiet
+            .into_iter()
+            .map(|settings| (settings, false))
+            .chain([(settings(), true)])
+        {
+            let (mut world, mut schedule) = world(settings);
+            world.insert_resource(NoAudioDevice(no_audio_device));
+            let snake = snake(&mut world, 5, 5);
+            played(&mut world, &mut schedule);
+
+            world.get_mut::<Snake>(snake).unwrap().direction = SnakeDirection::Up;
+            world.get_mut::<Score>(snake).unwrap().0 += 1;
+            world.entity_mut(snake).insert(Dead);
+            world
+                .resource_mut::<Input<KeyCode>>()
+                .press(KeyCode::Return);
+            assert!(played(&mut world, &mut schedule).is_empty());
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Chiptune music synthesized while it plays, so it needs no asset file.
+//!
+//! The music loops over four bars of `STEPS_PER_BAR` steps: a square wave arpeggio over the chord
+//! of the bar, a triangle bass and noise drums. Every step lasts as long as its [`Tempo`] says when
+//! it starts, and the game keeps the tempo at `STEPS_PER_MOVE` steps for every tick of the
+//! `MoveTimer`, so the music speeds up with the snake. [`render_wav`] writes the music to a file
+//! instead of playing it, which needs no audio device.
+
+use std::{
+    f32::consts::TAU,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    audio::{AddAudioSource, AudioSinkPlayback, Decodable, Source, Volume},
+    prelude::*,
+};
+
+use super::SoundSettings;
+use crate::MoveTimer;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+/// Steps of the music played during one tick of the game.
+pub const STEPS_PER_MOVE: u32 = 2;
+const STEPS_PER_BAR: usize = 16;
+const STEPS: usize = STEPS_PER_BAR * CHORDS.len();
+
+/// Am, F, C and G, as semitones above A4.
+const CHORDS: [[i32; 3]; 4] = [[0, 3, 7], [-4, 0, 3], [3, 7, 10], [-2, 2, 5]];
+/// The chord tone the arpeggio plays on every step of a bar.
+const ARPEGGIO: [usize; STEPS_PER_BAR] = [0, 1, 2, 1, 0, 1, 2, 1, 0, 2, 1, 2, 0, 2, 1, 2];
+
+/// Plays the music for as long as the game runs, at the pace of the `MoveTimer`.
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Music>()
+            .add_systems(Startup, start_music)
+            .add_systems(
+                Update,
+                (
+                    follow_moves,
+                    set_music_volume.run_if(resource_changed::<SoundSettings>()),
+                ),
+            );
+    }
+}
+
+/// The music as Bevy plays it, every sink it gets plays it from the start at the same tempo.
+#[derive(Asset, TypePath)]
+struct Music(Tempo);
+
+impl Decodable for Music {
+    type DecoderItem = f32;
+    type Decoder = Chiptune;
+
+    fn decoder(&self) -> Chiptune {
+        Chiptune::new(self.0.clone())
+    }
+}
+
+#[derive(Component)]
+struct MusicPlayer;
+
+fn start_music(
+    mut commands: Commands,
+    mut musics: ResMut<Assets<Music>>,
+    settings: Res<SoundSettings>,
+) {
+    let music = Music(Tempo::of_moves(MoveTimer::default().0.duration()));
+    commands.spawn((
+        AudioSourceBundle {
+            source: musics.add(music),
+            settings: PlaybackSettings::ONCE
+                .with_volume(Volume::new_relative(settings.volume(settings.music))),
+        },
+        MusicPlayer,
+    ));
+}
+
+fn follow_moves(
+    music_query: Query<&Handle<Music>, With<MusicPlayer>>,
+    musics: Res<Assets<Music>>,
+    move_timer_query: Query<&MoveTimer>,
+) {
+    let (Ok(handle), Ok(move_timer)) = (music_query.get_single(), move_timer_query.get_single())
+    else {
+        return;
+    };
+    if let Some(Music(tempo)) = musics.get(handle) {
+        tempo.set(move_timer.0.duration() / STEPS_PER_MOVE);
+    }
+}
+
+fn set_music_volume(
+    sink_query: Query<&AudioSink, With<MusicPlayer>>,
+    settings: Res<SoundSettings>,
+) {
+    for sink in &sink_query {
+        sink.set_volume(settings.volume(settings.music));
+    }
+}
+
+/// How long a step of the music lasts, shared between the game and the music playing.
+#[derive(Clone, Debug)]
+pub struct Tempo(Arc<AtomicU64>);
+
+impl Tempo {
+    #[must_use]
+    pub fn new(step: Duration) -> Self {
+        Self(Arc::new(AtomicU64::new(nanoseconds(step))))
+    }
+
+    /// The tempo of a game ticking every `interval`.
+    #[must_use]
+    pub fn of_moves(interval: Duration) -> Self {
+        Self::new(interval / STEPS_PER_MOVE)
+    }
+
+    /// Changes the length of the steps starting from now on.
+    pub fn set(&self, step: Duration) {
+        self.0.store(nanoseconds(step), Ordering::Relaxed);
+    }
+
+    fn step(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// The samples of the music, one channel at `SAMPLE_RATE`, going on forever.
+pub struct Chiptune {
+    tempo: Tempo,
+    step: usize,
+    /// Samples left in the current step, and how many it had.
+    remaining: u32,
+    length: u32,
+    lead_phase: f32,
+    bass_phase: f32,
+    kick_phase: f32,
+    /// State of the noise generator, a linear-feedback shift register like old sound chips had.
+    noise: u16,
+}
+
+impl Chiptune {
+    #[must_use]
+    pub const fn new(tempo: Tempo) -> Self {
+        Self {
+            tempo,
+            // The first sample moves on to the first step.
+            step: STEPS - 1,
+            remaining: 0,
+            length: 1,
+            lead_phase: 0.0,
+            bass_phase: 0.0,
+            kick_phase: 0.0,
+            noise: 1,
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if self.remaining == 0 {
+            self.step = (self.step + 1) % STEPS;
+            // At least a millisecond, so a broken tempo cannot stall the music.
+            self.length = samples(self.tempo.step().max(Duration::from_millis(1)));
+            self.remaining = self.length;
+        }
+        let elapsed = (self.length - self.remaining) as f32 / SAMPLE_RATE as f32;
+        let through = 1.0 - self.remaining as f32 / self.length as f32;
+        self.remaining -= 1;
+
+        let beat = self.step % STEPS_PER_BAR;
+        let chord = CHORDS[self.step / STEPS_PER_BAR];
+
+        // A quarter duty square wave, one octave up, fading out over the step.
+        self.lead_phase = (self.lead_phase + frequency(chord[ARPEGGIO[beat]] + 12)) % 1.0;
+        let lead = if self.lead_phase < 0.25 { 1.0 } else { -1.0 } * (1.0 - through) * 0.12;
+
+        // A triangle wave two octaves down, jumping an octave every other step.
+        let octave = if beat % 4 < 2 { -24 } else { -12 };
+        self.bass_phase = (self.bass_phase + frequency(chord[0] + octave)) % 1.0;
+        let bass = 4.0_f32.mul_add((self.bass_phase - 0.5).abs(), -1.0) * 0.25;
+
+        // A sine dropping in pitch on the beat, and short bursts of noise between beats.
+        let drum = match beat % 4 {
+            0 => {
+                self.kick_phase =
+                    (self.kick_phase + 150.0 * (-elapsed * 30.0).exp() / SAMPLE_RATE as f32) % 1.0;
+                (self.kick_phase * TAU).sin() * (-elapsed * 20.0).exp() * 0.4
+            }
+            2 => {
+                let bit = (self.noise ^ (self.noise >> 1)) & 1;
+                self.noise = (self.noise >> 1) | (bit << 14);
+                let noise = if self.noise & 1 == 0 { 1.0 } else { -1.0 };
+                noise * (-elapsed * 60.0).exp() * 0.1
+            }
+            _ => 0.0,
+        };
+
+        lead + bass + drum
+    }
+}
+
+impl Iterator for Chiptune {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.next_sample())
+    }
+}
+
+impl Source for Chiptune {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Writes `length` of the music, as played by a game ticking every `move_interval`, to a WAV file
+/// of 32-bit float samples.
+///
+/// # Errors
+///
+/// Fails if the file cannot be written.
+pub fn render_wav(path: &Path, length: Duration, move_interval: Duration) -> io::Result<()> {
+    let samples = samples(length);
+    let data_size = samples.saturating_mul(4);
+    let mut file = BufWriter::new(File::create(path)?);
+    file.write_all(b"RIFF")?;
+    file.write_all(&data_size.saturating_add(36).to_le_bytes())?;
+    file.write_all(b"WAVEfmt ")?;
+    file.write_all(&16_u32.to_le_bytes())?;
+    // Float samples, one channel.
+    file.write_all(&3_u16.to_le_bytes())?;
+    file.write_all(&1_u16.to_le_bytes())?;
+    file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    file.write_all(&(SAMPLE_RATE * 4).to_le_bytes())?;
+    file.write_all(&4_u16.to_le_bytes())?;
+    file.write_all(&32_u16.to_le_bytes())?;
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+    for sample in Chiptune::new(Tempo::of_moves(move_interval)).take(samples as usize) {
+        file.write_all(&sample.clamp(-1.0, 1.0).to_le_bytes())?;
+    }
+    file.flush()
+}
+
+/// How far a wave `semitones` above A4 moves in one sample.
+fn frequency(semitones: i32) -> f32 {
+    440.0 * (semitones as f32 / 12.0).exp2() / SAMPLE_RATE as f32
+}
+
+fn nanoseconds(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// The number of samples lasting `duration`.
+fn samples(duration: Duration) -> u32 {
+    u32::try_from(duration.as_nanos() * u128::from(SAMPLE_RATE) / 1_000_000_000).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_wav_writes_a_float_wav_file_of_the_length() {
+        let path = std::env::temp_dir().join(format!("snake-music-{}.wav", std::process::id()));
+        render_wav(
+            &path,
+            Duration::from_millis(500),
+            Duration::from_millis(150),
+        )
+        .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let half = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap());
+        let data_size = SAMPLE_RATE / 2 * 4;
+        assert_eq!(bytes.len(), 44 + data_size as usize);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(word(4), 36 + data_size);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!((word(16), half(20), half(22)), (16, 3, 1));
+        assert_eq!((word(24), word(28)), (SAMPLE_RATE, SAMPLE_RATE * 4));
+        assert_eq!((half(32), half(34)), (4, 32));
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(word(40), data_size);
+
+        let samples: Vec<f32> = bytes[44..]
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+            .collect();
+        assert!(samples.iter().all(|sample| (-1.0..=1.0).contains(sample)));
+        assert!(samples.iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn steps_follow_the_move_timer() {
+        let tempo = Tempo::of_moves(MoveTimer::default().0.duration());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Music>()
+            .add_systems(Update, follow_moves);
+        let handle = app
+            .world
+            .resource_mut::<Assets<Music>>()
+            .add(Music(tempo.clone()));
+        app.world.spawn((handle, MusicPlayer));
+        let timer = app.world.spawn(MoveTimer::default()).id();
+
+        let mut music = Chiptune::new(tempo.clone());
+        music.next();
+        assert_eq!(music.length, samples(Duration::from_millis(150)));
+
+        // The snake speeds up in the middle of a step, the next step is the first to be shorter.
+        let mut move_timer = app.world.get_mut::<MoveTimer>(timer).unwrap();
+        move_timer.0.set_duration(Duration::from_millis(100));
+        app.update();
+        assert_eq!(tempo.step(), Duration::from_millis(50));
+        for _ in 0..music.remaining {
+            music.next();
+        }
+        assert_eq!(music.length, samples(Duration::from_millis(150)));
+        music.next();
+        assert_eq!(music.length, samples(Duration::from_millis(50)));
+    }
+}
@@ -0,0 +1,411 @@
+//! Where every frame is on the sprite sheet, and the colours everything is tinted with.
+//!
+//! The sheet is a grid of tiles of `SPRITE_SIZE` pixels, described by `sprites.json` next to
+//! `sprites.png`. The metadata gives the size of the grid and the index of every frame the game
+//! draws, counting tiles left to right then top to bottom, so frames can be moved around or added
+//! to the sheet without touching the code. `palette.json` holds the colours, as `#rrggbb` or
+//! `#rrggbbaa`.
+//!
+//! The three files are read from the `assets` directory when the game starts, so changes to them
+//! show up without a rebuild. The game carries a copy of them for when there is no such directory,
+//! like once it is installed.
+//!
+//! Players can install skins, a directory holding those three files, in the `skins` directory of
+//! the game under their data directory, like `~/.local/share/snake-game-bevy/skins/neon` on Linux.
+//! A skin can leave out `sprites.json` or `palette.json` to keep the ones of the built-in sheet.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    asset::io::file::FileAssetReader,
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageSampler, ImageType},
+};
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{SnakeDirection, SPRITE_SIZE};
+
+/// The copy of the metadata of the sprite sheet built into the game.
+const EMBEDDED_FRAMES: &str = include_str!("../assets/sprites.json");
+const EMBEDDED_PALETTE: &str = include_str!("../assets/palette.json");
+
+/// Draws the game with the skin of the given name, or the sprites in the `assets` directory if
+/// there is none, falling back to the built-in sprites if they cannot be loaded.
+pub struct SkinPlugin(pub Option<String>);
+
+impl Plugin for SkinPlugin {
+    fn build(&self, app: &mut App) {
+        let skin = self.0.as_deref().map_or_else(asset_sprites, installed_skin);
+        let palette = skin
+            .as_ref()
+            .map_or_else(Palette::default, |skin| skin.palette.clone());
+        app.insert_resource(ClearColor(palette.background))
+            .insert_resource(palette);
+        if let Some(skin) = skin {
+            app.insert_resource(skin.frames.clone())
+                .insert_resource(skin);
+        }
+    }
+}
+
+fn installed_skin(name: &str) -> Option<Skin> {
+    Skin::load(name)
+        .map_err(|error| {
+            warn!("Could not load the skin {name}, using the built-in sprites: {error}");
+        })
+        .ok()
+}
+
+/// The sprites in the `assets` directory, if it has a sheet.
+fn asset_sprites() -> Option<Skin> {
+    let directory = FileAssetReader::get_base_path().join("assets");
+    if !directory.join("sprites.png").is_file() {
+        return None;
+    }
+    Skin::read(&directory)
+        .map_err(|error| {
+            warn!(
+                "Could not load the sprites in {}, using the built-in ones: {error}",
+                directory.display()
+            );
+        })
+        .ok()
+}
+
+/// A sprite sheet read when the game starts, from a skin installed by the player or the `assets`
+/// directory, used instead of the one built into the game.
+#[derive(Resource)]
+pub struct Skin {
+    pub sheet: Image,
+    pub frames: SpriteFrames,
+    pub palette: Palette,
+}
+
+impl Skin {
+    /// Reads the skin installed as `name`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the skin is not installed, or if its files cannot be read or do not fit together.
+    pub fn load(name: &str) -> io::Result<Self> {
+        let directory = skins_directory()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "there is no data directory"))?
+            .join(name);
+        if !directory.is_dir() {
+            return Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("there is no {}", directory.display()),
+            ));
+        }
+        Self::read(&directory)
+    }
+
+    /// Reads the sheet in `directory` with its metadata and palette, or the built-in ones if they
+    /// are missing.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the files cannot be read or do not fit together.
+    pub fn read(directory: &Path) -> io::Result<Self> {
+        let sheet = Image::from_buffer(
+            &fs::read(directory.join("sprites.png"))?,
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+        )
+        .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+        let frames = read_or_default(&directory.join("sprites.json"))?;
+        let palette = read_or_default(&directory.join("palette.json"))?;
+        check_frames(&frames, &sheet)?;
+        Ok(Self {
+            sheet,
+            frames,
+            palette,
+        })
+    }
+}
+
+/// Where skins are installed, one directory for each.
+#[must_use]
+pub fn skins_directory() -> Option<PathBuf> {
+    dirs::data_dir().map(|directory| directory.join("snake-game-bevy").join("skins"))
+}
+
+/// Reads the JSON file at `path`, or takes the built-in one if there is none.
+fn read_or_default<T: Default + for<'de> Deserialize<'de>>(path: &Path) -> io::Result<T> {
+    match fs::read(path) {
+        Ok(json) => Ok(serde_json::from_slice(&json)?),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Makes sure the sheet has every tile of the grid and every frame is on it.
+fn check_frames(frames: &SpriteFrames, sheet: &Image) -> io::Result<()> {
+    let size = sheet.size_f32();
+    if size.x < frames.columns as f32 * SPRITE_SIZE || size.y < frames.rows as f32 * SPRITE_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "a sheet of {}x{} pixels is too small for {}x{} tiles",
+                size.x, size.y, frames.columns, frames.rows
+            ),
+        ));
+    }
+    let tiles = frames.columns * frames.rows;
+    frames
+        .indices()
+        .find(|frame| *frame >= tiles)
+        .map_or(Ok(()), |frame| {
+            Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("frame {frame} is not on a sheet of {tiles} tiles"),
+            ))
+        })
+}
+
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct SpriteFrames {
+    /// Tiles across the sheet.
+    pub columns: usize,
+    /// Tiles down the sheet.
+    pub rows: usize,
+    /// Drawn behind the board, all around it.
+    pub glass: usize,
+    pub fruits: FruitFrames,
+    pub head: HeadFrames,
+    pub tail: TailFrames,
+    pub wall: WallFrames,
+    pub power_up: usize,
+    /// Played one after the other on the tail of a snake that died.
+    pub death: Vec<usize>,
+}
+
+impl SpriteFrames {
+    /// Every frame the game draws.
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        let (head, tail, wall) = (&self.head, &self.tail, &self.wall);
+        [
+            self.glass,
+            self.fruits.apple,
+            self.fruits.golden_apple,
+            self.fruits.berry,
+            self.fruits.poison,
+            self.fruits.bonus,
+            self.power_up,
+            head.up,
+            head.down,
+            head.right,
+            head.left,
+            tail.horizontal,
+            tail.vertical,
+            tail.down_right,
+            tail.down_left,
+            tail.up_right,
+            tail.up_left,
+            tail.end_left,
+            tail.end_up,
+            tail.end_down,
+            tail.end_right,
+            wall.top_bottom,
+            wall.left,
+            wall.right,
+            wall.top_left,
+            wall.top_right,
+            wall.bottom_left,
+            wall.bottom_right,
+        ]
+        .into_iter()
+        .chain(self.death.iter().copied())
+    }
+}
+
+impl Default for SpriteFrames {
+    fn default() -> Self {
+        serde_json::from_str(EMBEDDED_FRAMES).expect("the embedded sprite sheet metadata is valid")
+    }
+}
+
+/// Every type of fruit, and the bonus fruit.
+#[derive(Deserialize, Clone, Debug)]
+pub struct FruitFrames {
+    pub apple: usize,
+    pub golden_apple: usize,
+    pub berry: usize,
+    pub poison: usize,
+    pub bonus: usize,
+}
+
+/// The head of a snake heading in every direction.
+#[derive(Deserialize, Clone, Debug)]
+pub struct HeadFrames {
+    pub up: usize,
+    pub down: usize,
+    pub right: usize,
+    pub left: usize,
+}
+
+impl HeadFrames {
+    pub const fn facing(&self, direction: SnakeDirection) -> usize {
+        match direction {
+            SnakeDirection::Up => self.up,
+            SnakeDirection::Down => self.down,
+            SnakeDirection::Right => self.right,
+            SnakeDirection::Left => self.left,
+        }
+    }
+}
+
+/// The segments of a tail, the corners named after the two sides they join, and the tip of the
+/// tail named after the side the rest of the snake is on.
+#[derive(Deserialize, Clone, Debug)]
+pub struct TailFrames {
+    pub horizontal: usize,
+    pub vertical: usize,
+    pub down_right: usize,
+    pub down_left: usize,
+    pub up_right: usize,
+    pub up_left: usize,
+    pub end_left: usize,
+    pub end_up: usize,
+    pub end_down: usize,
+    pub end_right: usize,
+}
+
+/// The walls around the board, the corners named after where they are.
+#[derive(Deserialize, Clone, Debug)]
+pub struct WallFrames {
+    pub top_bottom: usize,
+    pub left: usize,
+    pub right: usize,
+    pub top_left: usize,
+    pub top_right: usize,
+    pub bottom_left: usize,
+    pub bottom_right: usize,
+}
+
+/// The colours the sprites are tinted with, and the one behind the board.
+#[derive(Resource, Deserialize, Clone, Debug)]
+pub struct Palette {
+    #[serde(deserialize_with = "hex_color")]
+    pub background: Color,
+    /// Tints of the snakes and score lines of every player.
+    #[serde(deserialize_with = "hex_colors")]
+    pub players: [Color; 4],
+    pub fruits: FruitColors,
+    pub power_ups: PowerUpColors,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        serde_json::from_str(EMBEDDED_PALETTE).expect("the embedded palette is valid")
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct FruitColors {
+    #[serde(deserialize_with = "hex_color")]
+    pub apple: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub golden_apple: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub berry: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub poison: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub bonus: Color,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct PowerUpColors {
+    #[serde(deserialize_with = "hex_color")]
+    pub ghost: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub slow_motion: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub shrink: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub magnet: Color,
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Color::hex(&hex).map_err(|error| de::Error::custom(format!("`{hex}` is not a colour: {error}")))
+}
+
+fn hex_colors<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[Color; N], D::Error> {
+    let colors = Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|hex| {
+            Color::hex(hex)
+                .map_err(|error| de::Error::custom(format!("`{hex}` is not a colour: {error}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = colors.len();
+    colors
+        .try_into()
+        .map_err(|_| de::Error::custom(format!("{count} colours given instead of {N}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assets() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets")
+    }
+
+    #[test]
+    fn the_assets_directory_matches_the_built_in_sprites() {
+        let skin = Skin::read(&assets()).unwrap();
+        assert!(skin.frames.indices().eq(SpriteFrames::default().indices()));
+        assert_eq!(skin.palette.background, Palette::default().background);
+    }
+
+    #[test]
+    fn every_fruit_has_its_own_frame() {
+        let fruits = SpriteFrames::default().fruits;
+        let mut frames = vec![
+            fruits.apple,
+            fruits.golden_apple,
+            fruits.berry,
+            fruits.poison,
+            fruits.bonus,
+        ];
+        frames.sort_unstable();
+        frames.dedup();
+        assert_eq!(frames.len(), 5);
+    }
+
+    #[test]
+    fn frames_off_the_sheet_are_rejected() {
+        let skin = Skin::read(&assets()).unwrap();
+        let frames = SpriteFrames {
+            power_up: skin.frames.columns * skin.frames.rows,
+            ..skin.frames.clone()
+        };
+        assert!(check_frames(&skin.frames, &skin.sheet).is_ok());
+        assert_eq!(
+            check_frames(&frames, &skin.sheet).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn colours_are_read_from_hex() {
+        let palette: Palette =
+            serde_json::from_str(&EMBEDDED_PALETTE.replace("\"#292a2b\"", "\"#ff000080\""))
+                .unwrap();
+        assert_eq!(palette.background, Color::rgba_u8(255, 0, 0, 128));
+        assert!(
+            serde_json::from_str::<Palette>(&EMBEDDED_PALETTE.replace("#292a2b", "#xyz")).is_err()
+        );
+    }
+}